@@ -0,0 +1,60 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       console.rs
+ * Purpose:    Where the menu reads input and writes output, so it can be
+ *             driven from the keyboard, a file or a script in the tests.
+ * =======================================================================
+ */
+
+use std::io::{BufRead, Write};
+
+pub trait Console {
+    // The next line without its line ending, or None once the input ends.
+    fn read_line(&mut self) -> Option<String>;
+
+    fn write(&mut self, text: &str);
+
+    fn write_line(&mut self, line: &str) {
+        self.write(line);
+        self.write("\n");
+    }
+}
+
+pub struct IoConsole<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> IoConsole<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        IoConsole { input, output }
+    }
+}
+
+impl<R: BufRead, W: Write> Console for IoConsole<R, W> {
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        let _ = self.output.write_all(text.as_bytes());
+        let _ = self.output.flush();
+    }
+}
+
+// The input ended while the menu was still asking for something.
+#[derive(Debug, PartialEq)]
+pub struct Eof;
+
+pub fn input(console: &mut dyn Console, text: &str) -> Result<String, Eof> {
+    console.write(&format!("{} ", text));
+    match console.read_line() {
+        Some(value) => Ok(value.trim().to_string()),
+        None => Err(Eof),
+    }
+}
@@ -6,122 +6,136 @@
  * =======================================================================
  */
 
+mod console;
 mod item;
 mod store;
 
-use item::Item;
+use console::{input, Console, Eof, IoConsole};
 use item::Product;
-use std::io::{self, Write};
-use store::{GroceryStore, Location, StoreError};
+use std::io;
+use store::{GroceryStore, Location};
 
 fn main() {
     let mut store = GroceryStore::<Product>::new();
+    let mut console = IoConsole::new(io::stdin().lock(), io::stdout());
+    run(&mut console, &mut store);
+}
 
+// Shows the menu until Exit is chosen or the input ends.
+fn run(console: &mut dyn Console, store: &mut GroceryStore<Product>) {
     loop {
-        println!("\n--- Menu ---");
-        println!("1: Add product");
-        println!("2: Remove product");
-        println!("3: Move product");
-        println!("4: Change product name");
-        println!("5: Change product price");
-        println!("6: Restock product");
-        println!("7: Show inventory");
-        println!("8: Find product location");
-        println!("9: Exit");
-
-        print!("Choose an option: ");
-        io::stdout().flush().unwrap();
-
-        let mut option = String::new();
-        io::stdin().read_line(&mut option).unwrap();
-
-        match option.trim() {
-            "1" => {
-                let (loc, product) = get();
-                match store.add_product(loc, product) {
-                    Ok(_) => println!("SUCESS!!!"),
-                    Err(e) => println!("Error: {:?}", e),
-                }
+        console.write_line("\n--- Menu ---");
+        console.write_line("1: Add product");
+        console.write_line("2: Remove product");
+        console.write_line("3: Move product");
+        console.write_line("4: Change product name");
+        console.write_line("5: Change product price");
+        console.write_line("6: Restock product");
+        console.write_line("7: Show inventory");
+        console.write_line("8: Find product location");
+        console.write_line("9: Exit");
+
+        let option = match input(console, "Choose an option:") {
+            Ok(option) => option,
+            Err(Eof) => break,
+        };
+        if option == "9" {
+            break;
+        }
+        if choose(console, store, &option) == Err(Eof) {
+            break;
+        }
+    }
+    console.write_line("");
+}
+
+fn choose(
+    console: &mut dyn Console,
+    store: &mut GroceryStore<Product>,
+    option: &str,
+) -> Result<(), Eof> {
+    match option {
+        "1" => {
+            let (loc, product) = get(console)?;
+            match store.add_product(loc, product) {
+                Ok(_) => console.write_line("SUCESS!!!"),
+                Err(e) => console.write_line(&format!("Error: {:?}", e)),
             }
-            "2" => {
-                let id = input("Enter product ID to remove:");
-                match store.remove_product(&id) {
-                    Ok(_) => println!("SUCESS!!!"),
-                    Err(e) => println!("Error: {:?}", e),
-                }
+        }
+        "2" => {
+            let id = input(console, "Enter product ID to remove:")?;
+            match store.remove_product(&id) {
+                Ok(_) => console.write_line("SUCESS!!!"),
+                Err(e) => console.write_line(&format!("Error: {:?}", e)),
             }
-            "3" => {
-                let id = input("Enter product ID to move:");
-                let new_loc = get_location();
-                match store.move_product(&id, new_loc) {
-                    Ok(_) => println!("SUCESS!!!"),
-                    Err(e) => println!("Error: {:?}", e),
-                }
+        }
+        "3" => {
+            let id = input(console, "Enter product ID to move:")?;
+            let new_loc = get_location(console)?;
+            match store.move_product(&id, new_loc) {
+                Ok(_) => console.write_line("SUCESS!!!"),
+                Err(e) => console.write_line(&format!("Error: {:?}", e)),
             }
-            "4" => {
-                let id = input("Enter product ID to rename:");
-                let name = input("Enter new name:");
-                match store.update_name(&id, name) {
-                    Ok(_) => println!("SUCESS!!!"),
-                    Err(e) => println!("Error: {:?}", e),
-                }
+        }
+        "4" => {
+            let id = input(console, "Enter product ID to rename:")?;
+            let name = input(console, "Enter new name:")?;
+            match store.update_name(&id, name) {
+                Ok(_) => console.write_line("SUCESS!!!"),
+                Err(e) => console.write_line(&format!("Error: {:?}", e)),
             }
-            "5" => {
-                let id = input("Enter product ID to update price:");
-                let price_str = input("Enter new price:");
-                if let Ok(price) = price_str.parse::<f64>() {
-                    match store.update_price(&id, price) {
-                        Ok(_) => println!("SUCESS!!"),
-                        Err(e) => println!("Error: {:?}", e),
-                    }
-                } else {
-                    println!("Sorry, invalid price.");
+        }
+        "5" => {
+            let id = input(console, "Enter product ID to update price:")?;
+            let price_str = input(console, "Enter new price:")?;
+            if let Ok(price) = price_str.parse::<f64>() {
+                match store.update_price(&id, price) {
+                    Ok(_) => console.write_line("SUCESS!!"),
+                    Err(e) => console.write_line(&format!("Error: {:?}", e)),
                 }
+            } else {
+                console.write_line("Sorry, invalid price.");
             }
-            "6" => {
-                let id = input("Enter product ID:");
-                let amount_str = input("Enter amount to add/remove:");
-                if let Ok(amount) = amount_str.parse::<i32>() {
-                    match store.restock(&id, amount) {
-                        Ok(_) => println!("SUCESS!!!"),
-                        Err(e) => println!("Error: {:?}", e),
-                    }
-                } else {
-                    println!("Sorry, invalid amount.");
+        }
+        "6" => {
+            let id = input(console, "Enter product ID:")?;
+            let amount_str = input(console, "Enter amount to add/remove:")?;
+            if let Ok(amount) = amount_str.parse::<i32>() {
+                match store.restock(&id, amount) {
+                    Ok(_) => console.write_line("SUCESS!!!"),
+                    Err(e) => console.write_line(&format!("Error: {:?}", e)),
                 }
+            } else {
+                console.write_line("Sorry, invalid amount.");
             }
-            "7" => store.print_inventory(),
-            "8" => {
-                let id = input("Enter product ID to search:");
-                match store.find_product(&id) {
-                    Some(loc) => println!(
-                        "Product '{}' is at Row: {}, Shelf: {}, Zone: {}",
-                        id, loc.row, loc.shelf, loc.zone
-                    ),
-                    None => println!("Sorry, product not found."),
-                }
+        }
+        "7" => {
+            for line in store.inventory_lines() {
+                console.write_line(&line);
+            }
+        }
+        "8" => {
+            let id = input(console, "Enter product ID to search:")?;
+            match store.find_product(&id) {
+                Some(loc) => console.write_line(&format!(
+                    "Product '{}' is at Row: {}, Shelf: {}, Zone: {}",
+                    id, loc.row, loc.shelf, loc.zone
+                )),
+                None => console.write_line("Sorry, product not found."),
             }
-            "9" => break,
-            _ => println!("Sorry, invalid option."),
         }
+        _ => console.write_line("Sorry, invalid option."),
     }
+    Ok(())
 }
 
-fn input(text: &str) -> String {
-    print!("{} ", text);
-    io::stdout().flush().unwrap();
-    let mut val = String::new();
-    io::stdin().read_line(&mut val).unwrap();
-    val.trim().to_string()
-}
-
-fn get() -> (Location, Product) {
-    let location = get_location();
-    let id = input("Product ID:");
-    let name = input("Name:");
-    let exp = input("Expiration date:");
-    let price = input("Price:");
-    let quantity = input("Quantity:");
+fn get(console: &mut dyn Console) -> Result<(Location, Product), Eof> {
+    let location = get_location(console)?;
+    let id = input(console, "Product ID:")?;
+    let name = input(console, "Name:")?;
+    let exp = input(console, "Expiration date:")?;
+    let price = input(console, "Price:")?;
+    let quantity = input(console, "Quantity:")?;
 
     let price: f64 = price.trim().parse().unwrap_or(0.0);
     let quantity: u32 = quantity.trim().parse().unwrap_or(0);
@@ -134,12 +148,50 @@ fn get() -> (Location, Product) {
         quantity,
     };
 
-    (location, product)
+    Ok((location, product))
+}
+
+fn get_location(console: &mut dyn Console) -> Result<Location, Eof> {
+    let row = input(console, "Row:")?;
+    let shelf = input(console, "Shelf:")?;
+    let zone = input(console, "Zone:")?;
+    Ok(Location { row, shelf, zone })
 }
 
-fn get_location() -> Location {
-    let row = input("Row:");
-    let shelf = input("Shelf:");
-    let zone = input("Zone:");
-    Location { row, shelf, zone }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Replays the typed lines against an empty store and returns the output.
+    fn session(script: &str) -> String {
+        let mut store = GroceryStore::<Product>::new();
+        let mut output = Vec::new();
+        run(
+            &mut IoConsole::new(script.as_bytes(), &mut output),
+            &mut store,
+        );
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_session_adds_restocks_and_finds() {
+        let output = session(
+            "1\nA\n2\n3\np1\nSoap\n2030-01-01\n1.5\n10\n6\np1\n-20\n6\np1\n5\n7\n8\np1\n9\n",
+        );
+
+        assert_eq!(output.matches("SUCESS!!!").count(), 2);
+        assert!(output.contains("Error: NotEnoughStock"));
+        assert!(output.contains("- Soap (Id: p1), Quantity: 15"));
+        assert!(output.contains("Product 'p1' is at Row: A, Shelf: 2, Zone: 3"));
+        assert_eq!(output.matches("--- Menu ---").count(), 6);
+    }
+
+    #[test]
+    fn test_session_ends_cleanly_on_eof() {
+        let output = session("42\n1\nA\n2\n");
+
+        assert!(output.contains("Sorry, invalid option."));
+        assert!(output.ends_with("Zone: \n"));
+        assert!(!output.contains("SUCESS"));
+    }
 }
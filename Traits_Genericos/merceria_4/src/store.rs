@@ -115,35 +115,37 @@ impl<T: Item> GroceryStore<T> {
         self.product_locations.get(id)
     }
 
-    pub fn print_inventory(&self) {
+    // The inventory as lines of text, one location after another.
+    pub fn inventory_lines(&self) -> Vec<String> {
         if self.inventory.is_empty() {
-            println!("Sorry, inventory is empty.");
-            return;
+            return vec!["Sorry, inventory is empty.".to_string()];
         }
 
+        let mut lines = vec![];
         for (loc, products) in &self.inventory {
             if products.is_empty() {
-                println!(
+                lines.push(format!(
                     "\nLocation - Row: {}, Shelf: {}, Zone: {} is empty.",
                     loc.row, loc.shelf, loc.zone
-                );
+                ));
                 continue;
             }
 
-            println!(
+            lines.push(format!(
                 "\nLocation - Row: {}, Shelf: {}, Zone: {}",
                 loc.row, loc.shelf, loc.zone
-            );
+            ));
             for p in products {
-                println!(
+                lines.push(format!(
                     "- {} (Id: {}), Quantity: {}, Expiration Date: {}, Price: {:.2}",
                     p.name(),
                     p.id(),
                     p.quantity(),
                     p.expiration_date(),
                     p.price()
-                );
+                ));
             }
         }
+        lines
     }
 }
@@ -0,0 +1,1161 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       cli.rs
+ * Purpose:    Non-interactive subcommands so scripts can drive the
+ *             warehouse without going through the menu.
+ * =======================================================================
+ */
+
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use warehouse::clock::{parse_date, FixedClock};
+use warehouse::csv;
+use warehouse::filter::Filter;
+use warehouse::item::{Item, Quality};
+use warehouse::journal::Recovery;
+use warehouse::layout::Layout;
+use warehouse::network::{Network, Transfer};
+use warehouse::report;
+use warehouse::rules;
+use warehouse::storage;
+use warehouse::strategy::StrategySpec;
+use warehouse::warehouse::{Location, PickOrder, Warehouse, WarehouseError, DEFAULT_EXPIRY_DAYS};
+
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+
+const USAGE: &str = "\
+Usage: warehouse [--file PATH] [--layout PATH] [--strategy NAME] [--rules PATH]
+                 [--today YYYY-MM-DD] [--script PATH] [--site NAME=PATH ...]
+                 [<command> [options]]
+
+Commands:
+  add       --id N --name NAME --quantity N [--quality normal|fragile|oversized]
+            [--expiry YYYY-MM-DD --max-shelf N] [--zones N] [--lot LOT]
+  find      --id N | --name NAME
+  lots      --id N            stock of an item per lot, soonest to expire first
+  remove    --row N --shelf N --zone N
+  pick      --id N --quantity N [--order fifo|fefo]
+  move      --row N --shelf N --zone N --to-row N --to-shelf N --to-zone N
+  compact   [--apply yes]     print the moves that pack every shelf
+  list
+  expiring  [--days N]        items expiring within N days (default 3)
+  quarantine                  block expired fragile items from being picked
+  report                      zones in use per row and shelf, and a heat map
+  import    --csv PATH        store the items listed in a CSV manifest
+  export    [--csv PATH]      write every stored item and its zone as CSV
+  receive   --csv PATH | --receipt N
+                              store a purchase-order receipt whole, or none of it
+  ship      --lines ID:N[,ID:N...] | --outbound N [--order fifo|fefo]
+                              pick an outbound order, as far as stock allows
+  route     --lines ID:N[,ID:N...] | --outbound N [--order fifo|fefo]
+                              the zones to pick from, in walking order
+  cancel    --receipt N | --outbound N
+  orders                      receipts and outbound orders with their status
+  where     --id N | --name NAME
+                              stock at every site given with --site
+  transfer  --from SITE --row N --shelf N --zone N --quantity N --to SITE
+                              send units from a zone at one site to another
+  help
+
+Without a command the interactive menu is started. The warehouse is read
+from and saved to --file (default: warehouse.json). When that file does not
+exist yet, an empty warehouse is built from --layout (default: layout.txt if
+present, otherwise 2 rows of 2 shelves of 3 zones). --strategy switches how
+new items are placed and is saved with the warehouse: closest, robin,
+emptiest, group (next to stock with the same name) or random[:SEED].
+New items must pass the rules in --rules (default: rules.txt if present,
+otherwise fragile items up to row 2 that have not expired). --today pins the
+date used for expiry checks and receipt times instead of the system clock.
+--script runs the menu with its input read from a file instead of the
+keyboard; the menu saves and exits when the input runs out.
+A receipt that does not fit stays pending and can be received again later;
+an outbound order short of stock is partially fulfilled until shipped again.
+Picks are listed in walking order. How far pickers walk and how the path is
+ordered are set in the layout with the walk, aisles and route statements.
+where and transfer work on the sites named with --site, each a warehouse
+file of its own; --file is not used then. A transfer is placed by the
+destination's own strategy and rules, and when the destination has no room
+neither site changes.
+An id stands for one product: adding it again under another name or
+quality fails, while new deliveries of it are stored next to the old ones.
+Exit codes: 0 on success, 1 when the operation fails or finds nothing,
+2 on bad usage.";
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Failed(String),
+}
+
+impl From<WarehouseError> for CliError {
+    fn from(e: WarehouseError) -> Self {
+        CliError::Failed(e.to_string())
+    }
+}
+
+struct Options {
+    values: HashMap<String, String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, CliError> {
+        let mut values = HashMap::new();
+        let mut iter = args.iter();
+
+        while let Some(flag) = iter.next() {
+            let key = flag
+                .strip_prefix("--")
+                .ok_or_else(|| CliError::Usage(format!("unexpected argument '{}'", flag)))?;
+            let value = iter
+                .next()
+                .ok_or_else(|| CliError::Usage(format!("missing value for --{}", key)))?;
+            values.insert(key.to_string(), value.clone());
+        }
+
+        Ok(Options { values })
+    }
+
+    fn allow(&self, allowed: &[&str]) -> Result<(), CliError> {
+        for key in self.values.keys() {
+            if !allowed.contains(&key.as_str()) {
+                return Err(CliError::Usage(format!("unknown option --{}", key)));
+            }
+        }
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(|v| v.as_str())
+    }
+
+    fn require(&self, key: &str) -> Result<&str, CliError> {
+        self.get(key)
+            .ok_or_else(|| CliError::Usage(format!("missing required option --{}", key)))
+    }
+
+    fn number<T: FromStr>(&self, key: &str) -> Result<Option<T>, CliError> {
+        match self.get(key) {
+            Some(value) => value.parse().map(Some).map_err(|_| {
+                CliError::Usage(format!("--{} expects a number, got '{}'", key, value))
+            }),
+            None => Ok(None),
+        }
+    }
+
+    fn require_number<T: FromStr>(&self, key: &str) -> Result<T, CliError> {
+        self.require(key)?;
+        Ok(self.number(key)?.unwrap())
+    }
+}
+
+// Options that apply to both the menu and the subcommands.
+pub struct Settings {
+    pub file: PathBuf,
+    pub layout: Option<PathBuf>,
+    pub strategy: Option<StrategySpec>,
+    pub rules: Option<PathBuf>,
+    pub today: Option<NaiveDate>,
+    pub script: Option<PathBuf>,
+    pub sites: Vec<(String, PathBuf)>,
+}
+
+// Splits the leading --file/--layout/--strategy/--rules/--today/--script/--site
+// options from the command.
+pub fn parse_settings(args: &[String]) -> Result<(Settings, &[String]), String> {
+    let mut settings = Settings {
+        file: PathBuf::from(storage::DEFAULT_SNAPSHOT_PATH),
+        layout: None,
+        strategy: None,
+        rules: None,
+        today: None,
+        script: None,
+        sites: vec![],
+    };
+    let mut args = args;
+
+    while let Some(flag) = args.first().filter(|a| {
+        matches!(
+            a.as_str(),
+            "--file" | "--layout" | "--strategy" | "--rules" | "--today" | "--script" | "--site"
+        )
+    }) {
+        let value = args
+            .get(1)
+            .ok_or_else(|| format!("missing value for {}", flag))?;
+        match flag.as_str() {
+            "--file" => settings.file = PathBuf::from(value),
+            "--layout" => settings.layout = Some(PathBuf::from(value)),
+            "--rules" => settings.rules = Some(PathBuf::from(value)),
+            "--today" => settings.today = Some(parse_date(value)?),
+            "--script" => settings.script = Some(PathBuf::from(value)),
+            "--site" => {
+                let (name, path) = value
+                    .split_once('=')
+                    .filter(|(name, path)| !name.is_empty() && !path.is_empty())
+                    .ok_or_else(|| format!("--site expects NAME=PATH, got '{}'", value))?;
+                settings.sites.push((name.to_string(), PathBuf::from(path)));
+            }
+            _ => settings.strategy = Some(StrategySpec::parse(value)?),
+        }
+        args = &args[2..];
+    }
+
+    Ok((settings, args))
+}
+
+// Loads the warehouse named by the settings, building it from the layout
+// when the file does not exist yet, and applies the chosen strategy.
+pub fn open_warehouse(settings: &Settings) -> Result<(Warehouse, Recovery), String> {
+    open_file(settings, &settings.file)
+}
+
+fn open_file(settings: &Settings, file: &Path) -> Result<(Warehouse, Recovery), String> {
+    let layout = Layout::resolve(settings.layout.as_deref()).map_err(|e| e.to_string())?;
+    let strategy = settings.strategy.clone().unwrap_or(StrategySpec::Closest);
+    let fresh = || Warehouse::from_layout(&layout, strategy.build());
+
+    let (mut warehouse, recovery) = storage::open(file, fresh)
+        .map_err(|e| format!("could not load {}: {}", file.display(), e))?;
+    if let Some(strategy) = &settings.strategy {
+        warehouse.set_strategy(strategy.build());
+    }
+    if let Some(today) = settings.today {
+        warehouse.set_clock(Box::new(FixedClock::on(today)));
+    }
+    Ok((warehouse, recovery))
+}
+
+// Every site named with --site, each opened like the single warehouse.
+fn open_network(settings: &Settings) -> Result<Network, String> {
+    let mut network = Network::new();
+    for (name, file) in &settings.sites {
+        let (warehouse, _) = open_file(settings, file)?;
+        network
+            .add_site(name, warehouse)
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(network)
+}
+
+// The filters new items must pass, from the rules file the settings name.
+pub fn load_rules(settings: &Settings) -> Result<Vec<Box<dyn Filter>>, String> {
+    rules::resolve(settings.rules.as_deref()).map_err(|e| e.to_string())
+}
+
+pub fn run(settings: &Settings, args: &[String]) -> i32 {
+    let file = &settings.file;
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => return usage_error("missing command"),
+    };
+
+    if matches!(command, "help" | "--help" | "-h") {
+        println!("{}", USAGE);
+        return EXIT_OK;
+    }
+
+    let options = match Options::parse(rest) {
+        Ok(options) => options,
+        Err(e) => return report(e),
+    };
+
+    if matches!(command, "where" | "transfer") {
+        return run_network(settings, command, &options);
+    }
+
+    let mut warehouse = match open_warehouse(settings) {
+        Ok((warehouse, _)) => warehouse,
+        Err(msg) => {
+            eprintln!("Error: {}", msg);
+            return EXIT_FAILURE;
+        }
+    };
+
+    let result = match command {
+        "add" => load_rules(settings)
+            .map_err(CliError::Failed)
+            .and_then(|filters| add(&mut warehouse, &options, &filters)),
+        "find" => find(&warehouse, &options),
+        "lots" => lots(&warehouse, &options),
+        "remove" => remove(&mut warehouse, &options),
+        "pick" => pick(&mut warehouse, &options),
+        "move" => move_item(&mut warehouse, &options),
+        "compact" => compact(&mut warehouse, &options),
+        "list" => list(&warehouse, &options),
+        "expiring" => expiring(&warehouse, &options),
+        "quarantine" => quarantine(&mut warehouse, &options),
+        "report" => report_occupancy(&warehouse, &options),
+        "import" => load_rules(settings)
+            .map_err(CliError::Failed)
+            .and_then(|filters| import(&mut warehouse, &options, &filters)),
+        "export" => export(&warehouse, &options),
+        "receive" => load_rules(settings)
+            .map_err(CliError::Failed)
+            .and_then(|filters| receive(&mut warehouse, &options, &filters)),
+        "ship" => ship(&mut warehouse, &options),
+        "cancel" => cancel(&mut warehouse, &options),
+        "route" => route(&warehouse, &options),
+        "orders" => list_orders(&warehouse, &options),
+        _ => Err(CliError::Usage(format!("unknown command '{}'", command))),
+    };
+
+    let changed = matches!(
+        command,
+        "add"
+            | "remove"
+            | "pick"
+            | "move"
+            | "compact"
+            | "quarantine"
+            | "import"
+            | "receive"
+            | "ship"
+            | "cancel"
+    ) || settings.strategy.is_some();
+    // An import with rejected lines still stored the others, and a receipt
+    // that did not fit is kept as pending.
+    if (result.is_ok() || matches!(command, "import" | "receive")) && changed {
+        if let Err(e) = storage::checkpoint(&mut warehouse, file) {
+            eprintln!("Error saving {}: {}", file.display(), e);
+            return EXIT_FAILURE;
+        }
+    }
+
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(e) => report(e),
+    }
+}
+
+// The commands that work across the sites given with --site.
+fn run_network(settings: &Settings, command: &str, options: &Options) -> i32 {
+    if settings.sites.is_empty() {
+        return usage_error(&format!("{} needs at least one --site NAME=PATH", command));
+    }
+    let mut network = match open_network(settings) {
+        Ok(network) => network,
+        Err(msg) => {
+            eprintln!("Error: {}", msg);
+            return EXIT_FAILURE;
+        }
+    };
+
+    let result = match command {
+        "where" => where_stored(&network, options),
+        _ => load_rules(settings)
+            .map_err(CliError::Failed)
+            .and_then(|filters| transfer(&mut network, options, &filters)),
+    };
+
+    if result.is_ok() && command == "transfer" {
+        for (name, file) in &settings.sites {
+            let warehouse = network.site_mut(name).expect("every site was opened");
+            if let Err(e) = storage::checkpoint(warehouse, file) {
+                eprintln!("Error saving site {} to {}: {}", name, file.display(), e);
+                return EXIT_FAILURE;
+            }
+        }
+    }
+
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(e) => report(e),
+    }
+}
+
+fn report(error: CliError) -> i32 {
+    match error {
+        CliError::Usage(msg) => usage_error(&msg),
+        CliError::Failed(msg) => {
+            eprintln!("{}", msg);
+            EXIT_FAILURE
+        }
+    }
+}
+
+fn print(lines: &[String]) {
+    for line in lines {
+        println!("{}", line);
+    }
+}
+
+pub fn usage_error(msg: &str) -> i32 {
+    eprintln!("Error: {}\n\n{}", msg, USAGE);
+    EXIT_USAGE
+}
+
+fn add(
+    warehouse: &mut Warehouse,
+    options: &Options,
+    filters: &[Box<dyn Filter>],
+) -> Result<(), CliError> {
+    options.allow(&[
+        "id",
+        "name",
+        "quantity",
+        "quality",
+        "expiry",
+        "max-shelf",
+        "zones",
+        "lot",
+    ])?;
+
+    let id = options.require_number("id")?;
+    let name = options.require("name")?.to_string();
+    let quantity = options.require_number("quantity")?;
+
+    let quality = match options.get("quality").unwrap_or("normal") {
+        "normal" => Quality::Normal,
+        "fragile" => Quality::Fragile {
+            expiry_date: parse_date(options.require("expiry")?).map_err(CliError::Usage)?,
+            max_shelf: options.require_number("max-shelf")?,
+        },
+        "oversized" => Quality::Oversized {
+            zones_needed: options.require_number("zones")?,
+        },
+        other => return Err(CliError::Usage(format!("unknown quality '{}'", other))),
+    };
+
+    let mut item = Item::new(id, name, quantity, quality, warehouse.now());
+    if let Some(lot) = options.get("lot") {
+        item = item.with_lot(lot);
+    }
+
+    for placement in warehouse.store(item, filters)? {
+        println!("Item stored at {}", placement);
+    }
+    Ok(())
+}
+
+fn find(warehouse: &Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&["id", "name"])?;
+
+    match (options.number::<u32>("id")?, options.get("name")) {
+        (Some(id), None) => {
+            print(&report::search_by_id(warehouse, id));
+            if warehouse.find_by_id(id).is_empty() {
+                return Err(CliError::Failed(format!("No item with ID {}", id)));
+            }
+        }
+        (None, Some(name)) => {
+            print(&report::search_by_name(warehouse, name));
+            if warehouse.count_by_name(name) == 0 {
+                return Err(CliError::Failed(format!("No item named '{}'", name)));
+            }
+        }
+        _ => {
+            return Err(CliError::Usage(
+                "find needs exactly one of --id or --name".to_string(),
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+fn lots(warehouse: &Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&["id"])?;
+
+    let id = options.require_number("id")?;
+    let lots = warehouse.lots(id);
+    for lot in &lots {
+        println!("{}", lot);
+    }
+    if lots.is_empty() {
+        return Err(CliError::Failed(format!("No item with ID {}", id)));
+    }
+    Ok(())
+}
+
+fn remove(warehouse: &mut Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&["row", "shelf", "zone"])?;
+
+    let row = options.require_number("row")?;
+    let shelf = options.require_number("shelf")?;
+    let zone = options.require_number("zone")?;
+
+    warehouse.remove_zone(row, shelf, zone)?;
+    println!(
+        "Item removed from Row {}, Shelf {}, Zone {}",
+        row, shelf, zone
+    );
+    Ok(())
+}
+
+fn pick(warehouse: &mut Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&["id", "quantity", "order"])?;
+
+    let id = options.require_number("id")?;
+    let quantity = options.require_number("quantity")?;
+    let order =
+        PickOrder::parse(options.get("order").unwrap_or("fifo")).map_err(CliError::Usage)?;
+
+    for pick in warehouse.pick(id, quantity, order)? {
+        println!("Picked from {}", pick);
+    }
+    Ok(())
+}
+
+fn move_item(warehouse: &mut Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&["row", "shelf", "zone", "to-row", "to-shelf", "to-zone"])?;
+
+    let from = Location::new(
+        options.require_number("row")?,
+        options.require_number("shelf")?,
+        options.require_number("zone")?,
+    );
+    let to = Location::new(
+        options.require_number("to-row")?,
+        options.require_number("to-shelf")?,
+        options.require_number("to-zone")?,
+    );
+
+    warehouse.move_item(from, to)?;
+    println!("Item moved from {} to {}", from, to);
+    Ok(())
+}
+
+fn compact(warehouse: &mut Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&["apply"])?;
+
+    let apply = match options.get("apply").unwrap_or("no") {
+        "yes" => true,
+        "no" => false,
+        other => {
+            return Err(CliError::Usage(format!(
+                "--apply expects yes or no, got '{}'",
+                other
+            )))
+        }
+    };
+
+    let plan = warehouse.compaction_plan();
+    for step in &plan.moves {
+        println!("{}", step);
+    }
+    println!("Total moves: {}", plan.moves.len());
+
+    if apply {
+        warehouse.apply_plan(&plan)?;
+        println!("Plan applied");
+    }
+    Ok(())
+}
+
+fn list(warehouse: &Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&[])?;
+
+    print(&report::all_items(warehouse));
+    Ok(())
+}
+
+fn expiring(warehouse: &Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&["days"])?;
+
+    let days = options.number("days")?.unwrap_or(DEFAULT_EXPIRY_DAYS);
+    print(&report::near_expiry(warehouse, days));
+    Ok(())
+}
+
+fn quarantine(warehouse: &mut Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&[])?;
+
+    let quarantined = warehouse.quarantine_expired()?;
+    for loc in &quarantined {
+        println!("Quarantined {}", loc);
+    }
+    println!("Items quarantined: {}", quarantined.len());
+    Ok(())
+}
+
+fn import(
+    warehouse: &mut Warehouse,
+    options: &Options,
+    filters: &[Box<dyn Filter>],
+) -> Result<(), CliError> {
+    options.allow(&["csv"])?;
+
+    let path = options.require("csv")?;
+    let text = fs::read_to_string(path)
+        .map_err(|e| CliError::Failed(format!("Could not read {}: {}", path, e)))?;
+    let reports = csv::import(warehouse, &text, filters);
+
+    print(&report::import_report(&reports));
+    let rejected = reports.iter().filter(|line| line.result.is_err()).count();
+    if rejected > 0 {
+        return Err(CliError::Failed(format!(
+            "Import incomplete: {} rejected",
+            rejected
+        )));
+    }
+    Ok(())
+}
+
+fn export(warehouse: &Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&["csv"])?;
+
+    let text = csv::export(warehouse);
+    match options.get("csv") {
+        Some(path) => fs::write(path, text)
+            .map_err(|e| CliError::Failed(format!("Could not write {}: {}", path, e))),
+        None => {
+            print!("{}", text);
+            Ok(())
+        }
+    }
+}
+
+fn receive(
+    warehouse: &mut Warehouse,
+    options: &Options,
+    filters: &[Box<dyn Filter>],
+) -> Result<(), CliError> {
+    options.allow(&["csv", "receipt"])?;
+
+    let id = match (options.get("csv"), options.number::<u32>("receipt")?) {
+        (Some(path), None) => {
+            let text = fs::read_to_string(path)
+                .map_err(|e| CliError::Failed(format!("Could not read {}: {}", path, e)))?;
+            let items = csv::read_items(warehouse, &text)
+                .map_err(|e| CliError::Failed(format!("Could not read {}: {}", path, e)))?;
+            let id = warehouse.create_receipt(items)?;
+            println!("Receipt {} created", id);
+            id
+        }
+        (None, Some(id)) => id,
+        _ => {
+            return Err(CliError::Usage(
+                "receive needs exactly one of --csv or --receipt".to_string(),
+            ))
+        }
+    };
+
+    let placements = warehouse.receive(id, filters)?;
+    for (index, placed) in placements.iter().enumerate() {
+        for placement in placed {
+            println!("Line {}: stored at {}", index + 1, placement);
+        }
+    }
+    println!("Receipt {} complete", id);
+    Ok(())
+}
+
+fn ship(warehouse: &mut Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&["lines", "outbound", "order"])?;
+
+    let pick_order =
+        PickOrder::parse(options.get("order").unwrap_or("fifo")).map_err(CliError::Usage)?;
+    let id = match (options.get("lines"), options.number::<u32>("outbound")?) {
+        (Some(text), None) => {
+            let id = warehouse.create_order(&order_lines(text)?)?;
+            println!("Order {} created", id);
+            id
+        }
+        (None, Some(id)) => id,
+        _ => {
+            return Err(CliError::Usage(
+                "ship needs exactly one of --lines or --outbound".to_string(),
+            ))
+        }
+    };
+
+    let path = warehouse.fulfil(id, pick_order)?;
+    print(&report::pick_path(&path));
+    println!("Order {} is {}", id, warehouse.outbound_order(id)?.status);
+    Ok(())
+}
+
+// Parses "ID:N,ID:N" into (item id, units) pairs.
+fn order_lines(text: &str) -> Result<Vec<(u32, u32)>, CliError> {
+    text.split(',')
+        .map(|pair| {
+            pair.split_once(':')
+                .and_then(|(id, quantity)| {
+                    Some((id.trim().parse().ok()?, quantity.trim().parse().ok()?))
+                })
+                .ok_or_else(|| {
+                    CliError::Usage(format!("--lines expects ID:N pairs, got '{}'", pair))
+                })
+        })
+        .collect()
+}
+
+fn route(warehouse: &Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&["lines", "outbound", "order"])?;
+
+    let pick_order =
+        PickOrder::parse(options.get("order").unwrap_or("fifo")).map_err(CliError::Usage)?;
+    let path = match (options.get("lines"), options.number::<u32>("outbound")?) {
+        (Some(text), None) => warehouse.pick_path(&order_lines(text)?, pick_order)?,
+        (None, Some(id)) => warehouse.order_path(id, pick_order)?,
+        _ => {
+            return Err(CliError::Usage(
+                "route needs exactly one of --lines or --outbound".to_string(),
+            ))
+        }
+    };
+
+    print(&report::pick_path(&path));
+    Ok(())
+}
+
+fn cancel(warehouse: &mut Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&["receipt", "outbound"])?;
+
+    match (
+        options.number::<u32>("receipt")?,
+        options.number::<u32>("outbound")?,
+    ) {
+        (Some(id), None) => {
+            warehouse.cancel_receipt(id)?;
+            println!("Receipt {} cancelled", id);
+        }
+        (None, Some(id)) => {
+            warehouse.cancel_order(id)?;
+            println!("Order {} cancelled", id);
+        }
+        _ => {
+            return Err(CliError::Usage(
+                "cancel needs exactly one of --receipt or --outbound".to_string(),
+            ))
+        }
+    }
+    Ok(())
+}
+
+fn list_orders(warehouse: &Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&[])?;
+
+    print(&report::orders(warehouse));
+    Ok(())
+}
+
+fn where_stored(network: &Network, options: &Options) -> Result<(), CliError> {
+    options.allow(&["id", "name"])?;
+
+    match (options.number::<u32>("id")?, options.get("name")) {
+        (Some(id), None) => {
+            print(&report::network_search_by_id(network, id));
+            if network.find_by_id(id).is_empty() {
+                return Err(CliError::Failed(format!(
+                    "No item with ID {} at any site",
+                    id
+                )));
+            }
+        }
+        (None, Some(name)) => {
+            print(&report::network_search_by_name(network, name));
+            if network.find_by_name(name).is_empty() {
+                return Err(CliError::Failed(format!(
+                    "No item named '{}' at any site",
+                    name
+                )));
+            }
+        }
+        _ => {
+            return Err(CliError::Usage(
+                "where needs exactly one of --id or --name".to_string(),
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+fn transfer(
+    network: &mut Network,
+    options: &Options,
+    filters: &[Box<dyn Filter>],
+) -> Result<(), CliError> {
+    options.allow(&["from", "row", "shelf", "zone", "quantity", "to"])?;
+
+    let order = Transfer {
+        from: options.require("from")?.to_string(),
+        location: Location::new(
+            options.require_number("row")?,
+            options.require_number("shelf")?,
+            options.require_number("zone")?,
+        ),
+        quantity: options.require_number("quantity")?,
+        to: options.require("to")?.to_string(),
+    };
+
+    let placements = network
+        .transfer(&order, filters)
+        .map_err(|e| CliError::Failed(e.to_string()))?;
+    println!("Transferred {}", order);
+    for placement in placements {
+        println!("Stored at {} in {}", placement, order.to);
+    }
+    Ok(())
+}
+
+fn report_occupancy(warehouse: &Warehouse, options: &Options) -> Result<(), CliError> {
+    options.allow(&[])?;
+
+    print(&report::occupancy_report(warehouse));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn run_line(list: &[&str]) -> i32 {
+        let all = args(list);
+        let (settings, command) = parse_settings(&all).unwrap();
+        run(&settings, command)
+    }
+
+    #[test]
+    fn test_options_parse() {
+        let options = Options::parse(&args(&["--id", "4", "--name", "Box"])).unwrap();
+        assert_eq!(options.require_number::<u32>("id").ok(), Some(4));
+        assert_eq!(options.get("name"), Some("Box"));
+        assert!(options.allow(&["id"]).is_err());
+        assert!(Options::parse(&args(&["--id"])).is_err());
+        assert!(Options::parse(&args(&["id", "4"])).is_err());
+    }
+
+    #[test]
+    fn test_add_find_remove_exit_codes() {
+        let path = std::env::temp_dir().join(format!("warehouse_cli_{}.json", std::process::id()));
+        let file = path.to_str().unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let add = [
+            "--file",
+            file,
+            "add",
+            "--id",
+            "9",
+            "--name",
+            "Crate",
+            "--quantity",
+            "2",
+        ];
+        assert_eq!(run_line(&add), EXIT_OK);
+        assert_eq!(run_line(&["--file", file, "find", "--id", "9"]), EXIT_OK);
+        assert_eq!(
+            run_line(&["--file", file, "find", "--id", "10"]),
+            EXIT_FAILURE
+        );
+        assert_eq!(run_line(&["--file", file, "find"]), EXIT_USAGE);
+
+        let pick = ["--file", file, "pick", "--id", "9", "--quantity", "1"];
+        assert_eq!(run_line(&pick), EXIT_OK);
+        assert_eq!(
+            run_line(&["--file", file, "pick", "--id", "9", "--quantity", "5"]),
+            EXIT_FAILURE
+        );
+        assert_eq!(
+            run_line(&[
+                "--file",
+                file,
+                "pick",
+                "--id",
+                "9",
+                "--quantity",
+                "1",
+                "--order",
+                "lifo"
+            ]),
+            EXIT_USAGE
+        );
+
+        let remove = [
+            "--file", file, "remove", "--row", "0", "--shelf", "0", "--zone", "0",
+        ];
+        assert_eq!(run_line(&remove), EXIT_OK);
+        assert_eq!(run_line(&remove), EXIT_FAILURE);
+        assert_eq!(
+            run_line(&["--file", file, "find", "--id", "9"]),
+            EXIT_FAILURE
+        );
+
+        let bad = [
+            "--file", file, "remove", "--row", "9", "--shelf", "0", "--zone", "0",
+        ];
+        assert_eq!(run_line(&bad), EXIT_FAILURE);
+        assert_eq!(run_line(&["--file", file, "launch"]), EXIT_USAGE);
+
+        std::fs::remove_file(&path).unwrap();
+        let _ = std::fs::remove_file(storage::journal_path(&path));
+    }
+
+    #[test]
+    fn test_layout_is_used_for_new_warehouse() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("warehouse_cli_layout_{}.json", std::process::id()));
+        let layout = dir.join(format!("warehouse_cli_layout_{}.txt", std::process::id()));
+        let file = path.to_str().unwrap();
+        let _ = std::fs::remove_file(&path);
+        std::fs::write(&layout, "row 1\n").unwrap();
+
+        let add = [
+            "--file",
+            file,
+            "--layout",
+            layout.to_str().unwrap(),
+            "add",
+            "--id",
+            "1",
+            "--name",
+            "Crate",
+            "--quantity",
+            "1",
+        ];
+        assert_eq!(run_line(&add), EXIT_OK);
+        // Later runs keep the saved warehouse, whose single zone is now taken.
+        let mut other = add;
+        other[6] = "2";
+        assert_eq!(run_line(&other), EXIT_FAILURE);
+
+        std::fs::write(&layout, "row 0\n").unwrap();
+        let list = [
+            "--file",
+            "missing.json",
+            "--layout",
+            layout.to_str().unwrap(),
+            "list",
+        ];
+        assert_eq!(run_line(&list), EXIT_FAILURE);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&layout).unwrap();
+        let _ = std::fs::remove_file(storage::journal_path(&path));
+    }
+
+    #[test]
+    fn test_dates_are_checked_when_typed() {
+        let path =
+            std::env::temp_dir().join(format!("warehouse_cli_dates_{}.json", std::process::id()));
+        let file = path.to_str().unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        let add = |today, expiry| {
+            run_line(&[
+                "--file",
+                file,
+                "--today",
+                today,
+                "add",
+                "--id",
+                "3",
+                "--name",
+                "Milk",
+                "--quantity",
+                "1",
+                "--quality",
+                "fragile",
+                "--expiry",
+                expiry,
+                "--max-shelf",
+                "1",
+            ])
+        };
+        assert_eq!(add("2030-01-01", "2030-02-30"), EXIT_USAGE);
+        assert_eq!(add("2030-03-01", "2030-02-28"), EXIT_FAILURE);
+        assert_eq!(add("2030-01-01", "2030-02-28"), EXIT_OK);
+        assert!(parse_settings(&args(&["--today", "soon", "list"])).is_err());
+
+        let later = |command: &[&str]| {
+            let mut line = vec!["--file", file, "--today", "2030-03-05"];
+            line.extend_from_slice(command);
+            run_line(&line)
+        };
+        assert_eq!(later(&["expiring", "--days", "0"]), EXIT_OK);
+        assert_eq!(
+            later(&["expiring", "--days", "9223372036854775807"]),
+            EXIT_OK
+        );
+        assert_eq!(later(&["quarantine"]), EXIT_OK);
+        assert_eq!(
+            later(&["pick", "--id", "3", "--quantity", "1"]),
+            EXIT_FAILURE
+        );
+
+        std::fs::remove_file(&path).unwrap();
+        let _ = std::fs::remove_file(storage::journal_path(&path));
+    }
+
+    #[test]
+    fn test_receipts_and_outbound_orders() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("warehouse_cli_orders_{}.json", std::process::id()));
+        let layout = dir.join(format!("warehouse_cli_orders_{}.txt", std::process::id()));
+        let manifest = dir.join(format!("warehouse_cli_orders_{}.csv", std::process::id()));
+        let file = path.to_str().unwrap();
+        let _ = std::fs::remove_file(&path);
+        std::fs::write(&layout, "capacity 10\nrow 2\n").unwrap();
+        let line = |command: &[&str]| {
+            let mut line = vec!["--file", file, "--layout", layout.to_str().unwrap()];
+            line.extend_from_slice(command);
+            run_line(&line)
+        };
+
+        // 25 units need three zones and there are two.
+        std::fs::write(&manifest, "1,Bolts,5,,,,\n2,Nuts,20,,,,\n").unwrap();
+        let receive = ["receive", "--csv", manifest.to_str().unwrap()];
+        assert_eq!(line(&receive), EXIT_FAILURE);
+        assert_eq!(line(&["find", "--id", "1"]), EXIT_FAILURE);
+        std::fs::write(&manifest, "1,Bolts,5,,,,\n2,Nuts,5,,,,\n").unwrap();
+        assert_eq!(line(&receive), EXIT_OK);
+        assert_eq!(line(&["receive", "--receipt", "2"]), EXIT_FAILURE);
+        assert_eq!(line(&["cancel", "--receipt", "1"]), EXIT_OK);
+
+        assert_eq!(line(&["ship", "--lines", "1:3,2:8"]), EXIT_OK);
+        assert_eq!(line(&["ship", "--lines", "1:x"]), EXIT_USAGE);
+        assert_eq!(line(&["route", "--outbound", "1"]), EXIT_OK);
+        assert_eq!(line(&["route", "--lines", "1:2"]), EXIT_OK);
+        assert_eq!(line(&["route", "--lines", "1:3"]), EXIT_FAILURE);
+        assert_eq!(line(&["cancel", "--outbound", "1"]), EXIT_OK);
+        assert_eq!(line(&["ship", "--outbound", "1"]), EXIT_FAILURE);
+        assert_eq!(line(&["orders"]), EXIT_OK);
+
+        let warehouse = storage::load(&path).unwrap().unwrap();
+        let book = warehouse.orders();
+        let statuses: Vec<String> = book
+            .receipts
+            .iter()
+            .map(|receipt| receipt.status.to_string())
+            .chain(book.outbound.iter().map(|order| order.status.to_string()))
+            .collect();
+        assert_eq!(statuses, vec!["cancelled", "complete", "cancelled"]);
+        assert_eq!(book.outbound[0].lines[1].picked, 5);
+        assert_eq!(warehouse.available(1), 2);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&layout).unwrap();
+        std::fs::remove_file(&manifest).unwrap();
+        let _ = std::fs::remove_file(storage::journal_path(&path));
+    }
+
+    #[test]
+    fn test_transfer_between_sites() {
+        let dir = std::env::temp_dir();
+        let north = dir.join(format!("warehouse_cli_north_{}.json", std::process::id()));
+        let south = dir.join(format!("warehouse_cli_south_{}.json", std::process::id()));
+        let layout = dir.join(format!("warehouse_cli_sites_{}.txt", std::process::id()));
+        for path in [&north, &south] {
+            let _ = std::fs::remove_file(path);
+        }
+        std::fs::write(&layout, "capacity 10\nrow 2\n").unwrap();
+        let north_site = format!("north={}", north.display());
+        let south_site = format!("south={}", south.display());
+        let sites = |command: &[&str]| {
+            let mut line = vec![
+                "--layout",
+                layout.to_str().unwrap(),
+                "--site",
+                &north_site,
+                "--site",
+                &south_site,
+            ];
+            line.extend_from_slice(command);
+            run_line(&line)
+        };
+
+        let add = [
+            "--file",
+            north.to_str().unwrap(),
+            "--layout",
+            layout.to_str().unwrap(),
+            "add",
+            "--id",
+            "42",
+            "--name",
+            "Bolts",
+            "--quantity",
+            "15",
+        ];
+        assert_eq!(run_line(&add), EXIT_OK);
+        assert_eq!(sites(&["where", "--id", "42"]), EXIT_OK);
+        assert_eq!(sites(&["where", "--name", "nuts"]), EXIT_FAILURE);
+
+        let transfer = |quantity| {
+            sites(&[
+                "transfer",
+                "--from",
+                "north",
+                "--row",
+                "0",
+                "--shelf",
+                "0",
+                "--zone",
+                "0",
+                "--quantity",
+                quantity,
+                "--to",
+                "south",
+            ])
+        };
+        assert_eq!(transfer("4"), EXIT_OK);
+        assert_eq!(transfer("11"), EXIT_FAILURE);
+        assert_eq!(sites(&["transfer", "--from", "north"]), EXIT_USAGE);
+        assert_eq!(run_line(&["where", "--id", "42"]), EXIT_USAGE);
+
+        let saved = |path: &PathBuf| {
+            let warehouse = storage::load(path).unwrap().unwrap();
+            warehouse
+                .items()
+                .iter()
+                .map(|(_, item)| item.quantity)
+                .collect::<Vec<u32>>()
+        };
+        assert_eq!(saved(&north), vec![6, 5]);
+        assert_eq!(saved(&south), vec![4]);
+
+        for path in [&north, &south] {
+            std::fs::remove_file(path).unwrap();
+            let _ = std::fs::remove_file(storage::journal_path(path));
+        }
+        std::fs::remove_file(&layout).unwrap();
+    }
+
+    #[test]
+    fn test_rules_file_filters_added_items() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("warehouse_cli_rules_{}.json", std::process::id()));
+        let rules = dir.join(format!("warehouse_cli_rules_{}.txt", std::process::id()));
+        let file = path.to_str().unwrap();
+        let _ = std::fs::remove_file(&path);
+        std::fs::write(&rules, "forbid-name Bleach\nmax-quantity 10\n").unwrap();
+
+        let add = |name, quantity| {
+            run_line(&[
+                "--file",
+                file,
+                "--rules",
+                rules.to_str().unwrap(),
+                "add",
+                "--id",
+                "1",
+                "--name",
+                name,
+                "--quantity",
+                quantity,
+            ])
+        };
+        assert_eq!(add("Bleach", "1"), EXIT_FAILURE);
+        assert_eq!(add("Soap", "11"), EXIT_FAILURE);
+        assert_eq!(add("Soap", "10"), EXIT_OK);
+
+        std::fs::write(&rules, "max-quantity ten\n").unwrap();
+        assert_eq!(add("Soap", "1"), EXIT_FAILURE);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&rules).unwrap();
+        let _ = std::fs::remove_file(storage::journal_path(&path));
+    }
+}
@@ -0,0 +1,48 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       clock.rs
+ * Purpose:    Where the warehouse gets the current time from, so tests
+ *             can pin "today" instead of reading the system clock.
+ * =======================================================================
+ */
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+
+    fn today(&self) -> NaiveDate {
+        self.now().date_naive()
+    }
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+// Always reports the same moment.
+pub struct FixedClock(pub DateTime<Utc>);
+
+impl FixedClock {
+    // Midnight UTC at the start of `date`.
+    pub fn on(date: NaiveDate) -> FixedClock {
+        FixedClock(date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
+
+// Reads a date typed as YYYY-MM-DD.
+pub fn parse_date(text: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(text.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("'{}' is not a date (expected YYYY-MM-DD)", text.trim()))
+}
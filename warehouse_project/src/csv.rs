@@ -0,0 +1,287 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       csv.rs
+ * Purpose:    Bulk import of delivery manifests from CSV, and CSV export
+ *             of what is stored where.
+ * =======================================================================
+ */
+
+use crate::clock::parse_date;
+use crate::filter::Filter;
+use crate::item::{Item, Quality};
+use crate::warehouse::{Placement, Warehouse};
+
+/*
+ * Import format, one item per line; the header line is optional:
+ *
+ *   id,name,quantity,quality,expiry,max_shelf,zones,lot
+ *   1,Bolts,200,normal,,,
+ *   2,Milk,30,fragile,2030-01-15,1,,L-0115
+ *   3,"Crate, large",1,oversized,,,3
+ *
+ * An empty quality means normal. expiry and max_shelf are read for fragile
+ * items, zones for oversized ones. The lot column may be left out. Fields
+ * holding commas or quotes are put in double quotes, with quotes inside
+ * doubled.
+ */
+pub const IMPORT_HEADER: &str = "id,name,quantity,quality,expiry,max_shelf,zones,lot";
+
+pub const EXPORT_HEADER: &str =
+    "row,shelf,zone,id,name,quantity,quality,expiry,max_shelf,zones,lot,received,quarantined";
+
+// What became of one line of an import.
+#[derive(Debug)]
+pub struct LineReport {
+    pub line: usize,
+    pub result: Result<Vec<Placement>, String>,
+}
+
+// Stores every item line by line, through the filters and the allocator like
+// items added by hand. A bad or rejected line does not stop the others.
+pub fn import(
+    warehouse: &mut Warehouse,
+    text: &str,
+    filters: &[Box<dyn Filter>],
+) -> Vec<LineReport> {
+    let mut reports = vec![];
+
+    for (line, fields) in records(text) {
+        let result = fields
+            .and_then(|fields| item_from(&fields, warehouse))
+            .and_then(|item| warehouse.store(item, filters).map_err(|e| e.to_string()));
+        reports.push(LineReport { line, result });
+    }
+
+    reports
+}
+
+// Reads every item of a manifest without storing any, as for a receipt.
+// Fails on the first bad line.
+pub fn read_items(warehouse: &Warehouse, text: &str) -> Result<Vec<Item>, String> {
+    records(text)
+        .into_iter()
+        .map(|(line, fields)| {
+            fields
+                .and_then(|fields| item_from(&fields, warehouse))
+                .map_err(|reason| format!("line {}: {}", line, reason))
+        })
+        .collect()
+}
+
+// The fields of every non-blank line with its line number, without the
+// header line.
+fn records(text: &str) -> Vec<(usize, Result<Vec<String>, String>)> {
+    let mut records = vec![];
+    let mut first = true;
+
+    for (index, raw) in text.lines().enumerate() {
+        if raw.trim().is_empty() {
+            continue;
+        }
+        let fields = split_fields(raw);
+        if first && fields.as_ref().is_ok_and(|fields| is_header(fields)) {
+            first = false;
+            continue;
+        }
+        first = false;
+        records.push((index + 1, fields));
+    }
+
+    records
+}
+
+// Every stored item with the coordinates of the zone holding it, in grid order.
+pub fn export(warehouse: &Warehouse) -> String {
+    let mut lines = vec![EXPORT_HEADER.to_string()];
+
+    for (loc, item) in warehouse.items() {
+        let (expiry, max_shelf, zones) = match &item.quality {
+            Quality::Fragile {
+                expiry_date,
+                max_shelf,
+            } => (
+                expiry_date.to_string(),
+                max_shelf.to_string(),
+                String::new(),
+            ),
+            Quality::Oversized { zones_needed } => {
+                (String::new(), String::new(), zones_needed.to_string())
+            }
+            Quality::Normal => (String::new(), String::new(), String::new()),
+        };
+        let fields = [
+            loc.row.to_string(),
+            loc.shelf.to_string(),
+            loc.zone.to_string(),
+            item.id.to_string(),
+            quote(&item.name),
+            item.quantity.to_string(),
+            item.quality.name().to_string(),
+            expiry,
+            max_shelf,
+            zones,
+            quote(item.lot.as_deref().unwrap_or("")),
+            item.timestamp.to_rfc3339(),
+            item.quarantined.to_string(),
+        ];
+        lines.push(fields.join(","));
+    }
+
+    lines.join("\n") + "\n"
+}
+
+fn is_header(fields: &[String]) -> bool {
+    fields
+        .first()
+        .is_some_and(|field| field.eq_ignore_ascii_case("id"))
+}
+
+fn item_from(fields: &[String], warehouse: &Warehouse) -> Result<Item, String> {
+    if !(7..=8).contains(&fields.len()) {
+        return Err(format!(
+            "expected 7 or 8 fields ({}), found {}",
+            IMPORT_HEADER,
+            fields.len()
+        ));
+    }
+    let number = |column: &str, text: &str| {
+        text.parse::<u32>()
+            .map_err(|_| format!("{} '{}' is not a number", column, text))
+    };
+
+    let name = fields[1].clone();
+    if name.is_empty() {
+        return Err("the name is empty".to_string());
+    }
+    let quality = match fields[3].to_lowercase().as_str() {
+        "" | "normal" => Quality::Normal,
+        "fragile" => Quality::Fragile {
+            expiry_date: parse_date(&fields[4])?,
+            max_shelf: number("max_shelf", &fields[5])?,
+        },
+        "oversized" => Quality::Oversized {
+            zones_needed: number("zones", &fields[6])?,
+        },
+        other => return Err(format!("unknown quality '{}'", other)),
+    };
+
+    let item = Item::new(
+        number("id", &fields[0])?,
+        name,
+        number("quantity", &fields[2])?,
+        quality,
+        warehouse.now(),
+    );
+    match fields.get(7) {
+        Some(lot) if !lot.is_empty() => Ok(item.with_lot(lot)),
+        _ => Ok(item),
+    }
+}
+
+// Splits one line into trimmed fields, honouring double quotes.
+fn split_fields(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = vec![];
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, quoted) {
+            ('"', true) if chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            ('"', true) => quoted = false,
+            ('"', false) if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            (',', false) => fields.push(std::mem::take(&mut field).trim().to_string()),
+            _ => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("a quoted field is not closed".to_string());
+    }
+    fields.push(field.trim().to_string());
+
+    Ok(fields)
+}
+
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n']) || field.trim() != field {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FixedClock;
+    use crate::filter::default_filters;
+    use crate::layout::Layout;
+    use crate::strategy::Closest;
+    use crate::warehouse::Location;
+
+    #[test]
+    fn test_split_fields() {
+        assert_eq!(
+            split_fields(r#"3, "Crate, ""large""" ,1,,"#).unwrap(),
+            vec!["3", "Crate, \"large\"", "1", "", ""]
+        );
+        assert!(split_fields("1,\"Bolts,2").is_err());
+    }
+
+    #[test]
+    fn test_import_reports_each_line_and_export_lists_coordinates() {
+        let layout = Layout::parse("capacity 50\nrow 3 3").unwrap();
+        let mut warehouse = Warehouse::from_layout(&layout, Box::new(Closest));
+        warehouse.set_clock(Box::new(FixedClock::on(parse_date("2030-01-10").unwrap())));
+
+        let text = "\
+id,name,quantity,quality,expiry,max_shelf,zones,lot
+1,Bolts,80,normal,,,
+2,Milk,10,fragile,2030-01-01,0,,L1
+
+3,\"Crate, large\",1,oversized,,,3
+4,Glue,lots,,,,
+5,Tape,5,normal
+";
+        let reports = import(&mut warehouse, text, &default_filters());
+        let outcome: Vec<(usize, bool)> = reports
+            .iter()
+            .map(|report| (report.line, report.result.is_ok()))
+            .collect();
+        assert_eq!(
+            outcome,
+            vec![(2, true), (3, false), (5, true), (6, false), (7, false)]
+        );
+        assert_eq!(reports[0].result.as_ref().unwrap().len(), 2);
+        assert!(reports[1]
+            .result
+            .as_ref()
+            .unwrap_err()
+            .contains("expiration"));
+        assert!(reports[3].result.as_ref().unwrap_err().contains("'lots'"));
+        assert_eq!(
+            warehouse.find_by_name("crate, large"),
+            vec![Location::new(0, 1, 0)]
+        );
+
+        let exported = export(&warehouse);
+        let lines: Vec<&str> = exported.lines().collect();
+        assert_eq!(lines[0], EXPORT_HEADER);
+        assert_eq!(
+            lines[1],
+            "0,0,0,1,Bolts,50,normal,,,,,2030-01-10T00:00:00+00:00,false"
+        );
+        assert_eq!(
+            lines[3],
+            "0,1,0,3,\"Crate, large\",1,oversized,,,3,,2030-01-10T00:00:00+00:00,false"
+        );
+        assert_eq!(lines.len(), 4);
+    }
+}
@@ -7,11 +7,39 @@
  * =======================================================================
  */
 
-use crate::item::Item;
+use crate::item::{Item, Quality};
 use crate::warehouse::Warehouse;
+use std::fmt;
+
+// Why a filter turned an item away.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rejection {
+    pub filter: String,
+    pub reason: String,
+}
+
+impl Rejection {
+    pub fn new(filter: &str, reason: String) -> Rejection {
+        Rejection {
+            filter: filter.to_string(),
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.filter, self.reason)
+    }
+}
+
+pub type FilterResult = Result<(), Rejection>;
 
 pub trait Filter {
-    fn apply(&self, warehouse: &Warehouse, item: &Item) -> bool;
+    fn apply(&self, warehouse: &Warehouse, item: &Item) -> FilterResult;
+
+    // Shown to the user when the filter turns an item away.
+    fn name(&self) -> &str;
 }
 
 pub struct MaxRow {
@@ -25,14 +53,27 @@ impl MaxRow {
 }
 
 impl Filter for MaxRow {
-    fn apply(&self, _warehouse: &Warehouse, item: &Item) -> bool {
+    fn apply(&self, _warehouse: &Warehouse, item: &Item) -> FilterResult {
         match &item.quality {
-            crate::item::Quality::Fragile { max_shelf, .. } => *max_shelf <= self.max_row as u32,
-            _ => true,
+            Quality::Fragile { max_shelf, .. } if *max_shelf > self.max_row as u32 => {
+                Err(Rejection::new(
+                    self.name(),
+                    format!(
+                        "fragile items may not go above row {}, this one allows row {}",
+                        self.max_row, max_shelf
+                    ),
+                ))
+            }
+            _ => Ok(()),
         }
     }
+
+    fn name(&self) -> &str {
+        "max row"
+    }
 }
 
+#[derive(Default)]
 pub struct ExpirationFilter;
 
 impl ExpirationFilter {
@@ -42,10 +83,309 @@ impl ExpirationFilter {
 }
 
 impl Filter for ExpirationFilter {
-    fn apply(&self, _warehouse: &Warehouse, item: &Item) -> bool {
+    fn apply(&self, warehouse: &Warehouse, item: &Item) -> FilterResult {
         match &item.quality {
-            crate::item::Quality::Fragile { .. } => !item.is_expired(),
-            _ => true, // normal  oversized not filter
+            Quality::Fragile { expiry_date, .. } if item.is_expired(warehouse.today()) => Err(
+                Rejection::new(self.name(), format!("the item expired on {}", expiry_date)),
+            ),
+            _ => Ok(()), // normal  oversized not filter
+        }
+    }
+
+    fn name(&self) -> &str {
+        "expiration"
+    }
+}
+
+// Fragile items must have at least this many days left before they expire.
+pub struct MinDaysToExpiry {
+    pub days: i64,
+}
+
+impl Filter for MinDaysToExpiry {
+    fn apply(&self, warehouse: &Warehouse, item: &Item) -> FilterResult {
+        let Some(date) = item.expiry_date() else {
+            return Ok(());
+        };
+        let days_left = (date - warehouse.today()).num_days();
+        if days_left < self.days {
+            return Err(Rejection::new(
+                self.name(),
+                format!(
+                    "the item has {} days left, at least {} are needed",
+                    days_left, self.days
+                ),
+            ));
         }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "min days to expiry"
+    }
+}
+
+// Refuses items with this name, ignoring case.
+pub struct ForbiddenName {
+    pub name: String,
+}
+
+impl Filter for ForbiddenName {
+    fn apply(&self, _warehouse: &Warehouse, item: &Item) -> FilterResult {
+        if item.name.eq_ignore_ascii_case(&self.name) {
+            return Err(Rejection::new(
+                self.name(),
+                format!("'{}' may not be stored here", item.name),
+            ));
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "forbidden name"
+    }
+}
+
+// Refuses deliveries of more than `max` units at once.
+pub struct MaxQuantity {
+    pub max: u32,
+}
+
+impl Filter for MaxQuantity {
+    fn apply(&self, _warehouse: &Warehouse, item: &Item) -> FilterResult {
+        if item.quantity > self.max {
+            return Err(Rejection::new(
+                self.name(),
+                format!(
+                    "{} units is more than the limit of {}",
+                    item.quantity, self.max
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> &str {
+        "max quantity"
+    }
+}
+
+// Applies the inner filter only to items of one quality ("normal",
+// "fragile" or "oversized"); the rest pass.
+pub struct ForQuality {
+    quality: String,
+    filter: Box<dyn Filter>,
+    name: String,
+}
+
+impl ForQuality {
+    pub fn new(quality: &str, filter: Box<dyn Filter>) -> Self {
+        let name = format!("{} {}", quality, filter.name());
+        ForQuality {
+            quality: quality.to_string(),
+            filter,
+            name,
+        }
+    }
+}
+
+impl Filter for ForQuality {
+    fn apply(&self, warehouse: &Warehouse, item: &Item) -> FilterResult {
+        if item.quality.name() != self.quality {
+            return Ok(());
+        }
+        self.filter
+            .apply(warehouse, item)
+            .map_err(|rejection| Rejection::new(&self.name, rejection.reason))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// Passes when every filter passes; reports the first rejection.
+pub struct And {
+    filters: Vec<Box<dyn Filter>>,
+    name: String,
+}
+
+impl And {
+    pub fn new(filters: Vec<Box<dyn Filter>>) -> Self {
+        let name = joined_names(&filters, " and ");
+        And { filters, name }
+    }
+}
+
+impl Filter for And {
+    fn apply(&self, warehouse: &Warehouse, item: &Item) -> FilterResult {
+        self.filters
+            .iter()
+            .try_for_each(|filter| filter.apply(warehouse, item))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// Passes when any filter passes; otherwise lists every reason.
+pub struct Or {
+    filters: Vec<Box<dyn Filter>>,
+    name: String,
+}
+
+impl Or {
+    pub fn new(filters: Vec<Box<dyn Filter>>) -> Self {
+        let name = joined_names(&filters, " or ");
+        Or { filters, name }
+    }
+}
+
+impl Filter for Or {
+    fn apply(&self, warehouse: &Warehouse, item: &Item) -> FilterResult {
+        let mut reasons = vec![];
+        for filter in &self.filters {
+            match filter.apply(warehouse, item) {
+                Ok(()) => return Ok(()),
+                Err(rejection) => reasons.push(rejection.to_string()),
+            }
+        }
+
+        if reasons.is_empty() {
+            reasons.push("no filter to pass".to_string());
+        }
+        Err(Rejection::new(self.name(), reasons.join("; ")))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// Passes when the inner filter rejects the item.
+pub struct Not {
+    filter: Box<dyn Filter>,
+    name: String,
+}
+
+impl Not {
+    pub fn new(filter: Box<dyn Filter>) -> Self {
+        let name = format!("not {}", filter.name());
+        Not { filter, name }
+    }
+}
+
+impl Filter for Not {
+    fn apply(&self, warehouse: &Warehouse, item: &Item) -> FilterResult {
+        match self.filter.apply(warehouse, item) {
+            Ok(()) => Err(Rejection::new(
+                self.name(),
+                format!("the item passes {}", self.filter.name()),
+            )),
+            Err(_) => Ok(()),
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// Gives a filter (often a combination) a name of its own. Rejections keep
+// the inner reason but report this name.
+pub struct Named {
+    name: String,
+    filter: Box<dyn Filter>,
+}
+
+impl Named {
+    pub fn new(name: &str, filter: Box<dyn Filter>) -> Self {
+        Named {
+            name: name.to_string(),
+            filter,
+        }
+    }
+}
+
+impl Filter for Named {
+    fn apply(&self, warehouse: &Warehouse, item: &Item) -> FilterResult {
+        self.filter
+            .apply(warehouse, item)
+            .map_err(|rejection| Rejection::new(&self.name, rejection.to_string()))
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+fn joined_names(filters: &[Box<dyn Filter>], separator: &str) -> String {
+    let names: Vec<&str> = filters.iter().map(|filter| filter.name()).collect();
+    format!("({})", names.join(separator))
+}
+
+// The filters every new item goes through before it is stored.
+pub fn default_filters() -> Vec<Box<dyn Filter>> {
+    vec![Box::new(MaxRow::new(2)), Box::new(ExpirationFilter::new())]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{parse_date, FixedClock};
+    use crate::layout::Layout;
+    use crate::strategy::Closest;
+    use chrono::DateTime;
+
+    fn fragile(max_shelf: u32, expiry_date: &str) -> Item {
+        let quality = Quality::Fragile {
+            expiry_date: parse_date(expiry_date).unwrap(),
+            max_shelf,
+        };
+        Item::new(1, "Glass".to_string(), 1, quality, DateTime::UNIX_EPOCH)
+    }
+
+    #[test]
+    fn test_combinators_explain_rejections() {
+        let mut warehouse = Warehouse::from_layout(&Layout::default(), Box::new(Closest));
+        warehouse.set_clock(Box::new(FixedClock::on(parse_date("2030-01-10").unwrap())));
+        let high_and_old = fragile(5, "2030-01-09");
+        let fresh = fragile(0, "2030-01-10");
+
+        let both = And::new(vec![
+            Box::new(MaxRow::new(2)),
+            Box::new(ExpirationFilter::new()),
+        ]);
+        assert_eq!(both.name(), "(max row and expiration)");
+        assert!(both.apply(&warehouse, &fresh).is_ok());
+        assert_eq!(
+            both.apply(&warehouse, &high_and_old).unwrap_err().filter,
+            "max row"
+        );
+
+        let either = Or::new(vec![
+            Box::new(MaxRow::new(2)),
+            Box::new(ExpirationFilter::new()),
+        ]);
+        let rejection = either.apply(&warehouse, &high_and_old).unwrap_err();
+        assert_eq!(rejection.filter, "(max row or expiration)");
+        assert!(rejection.reason.contains("max row: "));
+        assert!(rejection
+            .reason
+            .contains("expiration: the item expired on 2030-01-09"));
+        assert!(either.apply(&warehouse, &fragile(5, "2030-01-10")).is_ok());
+
+        let only_expired = Named::new(
+            "write-off",
+            Box::new(Not::new(Box::new(ExpirationFilter::new()))),
+        );
+        assert!(only_expired.apply(&warehouse, &high_and_old).is_ok());
+        let rejection = only_expired.apply(&warehouse, &fresh).unwrap_err();
+        assert_eq!(rejection.filter, "write-off");
+        assert_eq!(
+            rejection.reason,
+            "not expiration: the item passes expiration"
+        );
     }
 }
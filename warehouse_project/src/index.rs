@@ -0,0 +1,115 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       index.rs
+ * Purpose:    Lookup tables from item id, name and expiry date to the
+ *             zones holding the item, so queries skip the grid scan.
+ * =======================================================================
+ */
+
+use crate::item::Item;
+use crate::warehouse::Location;
+use chrono::NaiveDate;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Every location here is the zone that holds the item itself, never the
+// extra zones an oversized item reserves. Sets keep locations in grid order.
+#[derive(Debug, Default, PartialEq)]
+pub struct ItemIndex {
+    by_id: BTreeMap<u32, BTreeSet<Location>>,
+    // Keyed by lowercase name, so lookups ignore case and iterate sorted.
+    by_name: BTreeMap<String, BTreeSet<Location>>,
+    by_expiry: BTreeMap<NaiveDate, BTreeSet<Location>>,
+}
+
+impl ItemIndex {
+    pub fn insert(&mut self, loc: Location, item: &Item) {
+        self.by_id.entry(item.id).or_default().insert(loc);
+        self.by_name
+            .entry(item.name.to_lowercase())
+            .or_default()
+            .insert(loc);
+        if let Some(date) = item.expiry_date() {
+            self.by_expiry.entry(date).or_default().insert(loc);
+        }
+    }
+
+    pub fn remove(&mut self, loc: Location, item: &Item) {
+        remove_from(&mut self.by_id, &item.id, loc);
+        remove_from(&mut self.by_name, &item.name.to_lowercase(), loc);
+        if let Some(date) = item.expiry_date() {
+            remove_from(&mut self.by_expiry, &date, loc);
+        }
+    }
+
+    pub fn by_id(&self, id: u32) -> Vec<Location> {
+        self.by_id
+            .get(&id)
+            .map(|locs| locs.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn by_name(&self, name: &str) -> Vec<Location> {
+        self.by_name
+            .get(&name.to_lowercase())
+            .map(|locs| locs.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    // All indexed locations, ordered by lowercase item name.
+    pub fn sorted_by_name(&self) -> Vec<Location> {
+        self.by_name.values().flatten().copied().collect()
+    }
+
+    // Locations of items expiring on or before `date`, soonest first.
+    pub fn expiring_by(&self, date: NaiveDate) -> Vec<(NaiveDate, Location)> {
+        self.by_expiry
+            .range(..=date)
+            .flat_map(|(date, locs)| locs.iter().map(move |loc| (*date, *loc)))
+            .collect()
+    }
+}
+
+// Drops `loc` from the key's set, and the key once nothing is left.
+fn remove_from<K: Ord>(map: &mut BTreeMap<K, BTreeSet<Location>>, key: &K, loc: Location) {
+    if let Some(locs) = map.get_mut(key) {
+        locs.remove(&loc);
+        if locs.is_empty() {
+            map.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::parse_date;
+    use crate::item::Quality;
+    use chrono::DateTime;
+
+    fn milk(id: u32, expiry: &str) -> Item {
+        let quality = Quality::Fragile {
+            expiry_date: parse_date(expiry).unwrap(),
+            max_shelf: 1,
+        };
+        Item::new(id, "Milk".to_string(), 1, quality, DateTime::UNIX_EPOCH)
+    }
+
+    #[test]
+    fn test_insert_query_and_remove() {
+        let mut index = ItemIndex::default();
+        let first = Location::new(0, 0, 1);
+        let second = Location::new(0, 0, 0);
+        index.insert(first, &milk(1, "2030-01-02"));
+        index.insert(second, &milk(1, "2030-01-01"));
+
+        assert_eq!(index.by_id(1), vec![second, first]);
+        assert_eq!(index.by_name("MILK"), vec![second, first]);
+        let jan_1 = NaiveDate::from_ymd_opt(2030, 1, 1).unwrap();
+        assert_eq!(index.expiring_by(jan_1), vec![(jan_1, second)]);
+
+        index.remove(second, &milk(1, "2030-01-01"));
+        index.remove(first, &milk(1, "2030-01-02"));
+        assert_eq!(index, ItemIndex::default());
+    }
+}
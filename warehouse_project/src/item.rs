@@ -2,54 +2,126 @@
  * =======================================================================
  * Author:     Rita Ferreira
  * File:       item.rs
- * Purpose:    Represents items with quantity, quality types, and 
+ * Purpose:    Represents items with quantity, quality types, and
  *            expiration logic for fragile goods.
  * =======================================================================
  */
 
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
 
-use chrono::{NaiveDate, Utc};
-
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Quality {
-    Fragile { expiry_date: String, max_shelf: u32 },
-    Oversized { zones_needed: u32 },
+    // `max_shelf` is the highest row the item may be stored in.
+    Fragile {
+        expiry_date: NaiveDate,
+        max_shelf: u32,
+    },
+    Oversized {
+        zones_needed: u32,
+    },
     Normal,
 }
 
+impl Quality {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Quality::Fragile { .. } => "fragile",
+            Quality::Oversized { .. } => "oversized",
+            Quality::Normal => "normal",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Item {
     pub id: u32,
     pub name: String,
     pub quantity: u32,
     pub quality: Quality,
-    pub timestamp: String,
+    // When the item was received.
+    pub timestamp: DateTime<Utc>,
+    // The supplier's lot (batch) number, when the delivery has one.
+    #[serde(default)]
+    pub lot: Option<String>,
+    // Expired stock set aside so it can no longer be picked.
+    #[serde(default)]
+    pub quarantined: bool,
 }
 
 impl Item {
-    pub fn new(id: u32, name: String, quantity: u32, quality: Quality, timestamp: String) -> Self {
+    pub fn new(
+        id: u32,
+        name: String,
+        quantity: u32,
+        quality: Quality,
+        timestamp: DateTime<Utc>,
+    ) -> Self {
         Item {
             id,
             name,
             quantity,
             quality,
             timestamp,
+            lot: None,
+            quarantined: false,
         }
     }
 
+    pub fn with_lot(mut self, lot: &str) -> Self {
+        self.lot = Some(lot.to_string());
+        self
+    }
+
     pub fn details(&self) -> String {
-        format!(
+        let mut details = format!(
             "ID: {}, Name: {}, Quantity: {}, Quality: {:?}, Timestamp: {}",
             self.id, self.name, self.quantity, self.quality, self.timestamp
-        )
+        );
+        if let Some(lot) = &self.lot {
+            details = format!("{}, Lot: {}", details, lot);
+        }
+        if self.quarantined {
+            format!("{} (quarantined)", details)
+        } else {
+            details
+        }
     }
 
-    pub fn is_expired(&self) -> bool {
-        if let Quality::Fragile { expiry_date, .. } = &self.quality {
-            if let Ok(date) = NaiveDate::parse_from_str(&expiry_date, "%Y-%m-%d") {
-                let today = Utc::now().naive_utc().date();
-                return date < today;
-            }
+    // An id names one product (SKU): the same name, ignoring case, and the
+    // same kind of quality. Deliveries of it may still differ, e.g. in expiry
+    // date, and are then stored apart.
+    pub fn same_sku(&self, other: &Item) -> bool {
+        self.id == other.id
+            && self.name.eq_ignore_ascii_case(&other.name)
+            && self.quality.name() == other.quality.name()
+    }
+
+    // Units of the same stock can share a zone.
+    pub fn same_stock(&self, other: &Item) -> bool {
+        self.id == other.id
+            && self.name == other.name
+            && self.quality == other.quality
+            && self.lot == other.lot
+            && self.quarantined == other.quarantined
+    }
+
+    // Number of neighbouring zones on one shelf the item takes up.
+    pub fn zones_needed(&self) -> usize {
+        match &self.quality {
+            Quality::Oversized { zones_needed } => (*zones_needed as usize).max(1),
+            _ => 1,
         }
-        false
+    }
+
+    pub fn expiry_date(&self) -> Option<NaiveDate> {
+        match &self.quality {
+            Quality::Fragile { expiry_date, .. } => Some(*expiry_date),
+            _ => None,
+        }
+    }
+
+    pub fn is_expired(&self, today: NaiveDate) -> bool {
+        self.expiry_date().is_some_and(|date| date < today)
     }
 }
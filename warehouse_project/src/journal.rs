@@ -0,0 +1,354 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       journal.rs
+ * Purpose:    Append-only event journal of warehouse operations, used
+ *             to rebuild the warehouse after a crash.
+ * =======================================================================
+ */
+
+use crate::item::Item;
+use crate::orders::{OutboundOrder, Receipt};
+use crate::warehouse::{Location, Warehouse, WarehouseError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Event {
+    ItemStored {
+        row: usize,
+        shelf: usize,
+        zone: usize,
+        item: Item,
+    },
+    ItemRemoved {
+        row: usize,
+        shelf: usize,
+        zone: usize,
+    },
+    Restocked {
+        row: usize,
+        shelf: usize,
+        zone: usize,
+        quantity: u32,
+    },
+    Picked {
+        row: usize,
+        shelf: usize,
+        zone: usize,
+        quantity: u32,
+    },
+    ItemMoved {
+        from: Location,
+        to: Location,
+    },
+    Quarantined {
+        row: usize,
+        shelf: usize,
+        zone: usize,
+    },
+    // The whole receipt or order as it stands after a change.
+    ReceiptSaved {
+        receipt: Receipt,
+    },
+    OrderSaved {
+        order: OutboundOrder,
+    },
+}
+
+#[derive(Serialize)]
+struct RecordOut<'a> {
+    seq: u64,
+    event: &'a Event,
+}
+
+#[derive(Deserialize)]
+struct RecordIn {
+    seq: u64,
+    event: Event,
+}
+
+#[derive(Debug)]
+pub enum JournalError {
+    Io(io::Error),
+    Corrupt { line: usize, reason: String },
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JournalError::Io(e) => write!(f, "could not access journal file: {}", e),
+            JournalError::Corrupt { line, reason } => {
+                write!(f, "journal is corrupt at line {}: {}", line, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for JournalError {}
+
+impl From<io::Error> for JournalError {
+    fn from(e: io::Error) -> Self {
+        JournalError::Io(e)
+    }
+}
+
+pub struct Journal {
+    file: File,
+}
+
+impl Journal {
+    pub fn open(path: &Path) -> io::Result<Journal> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Journal { file })
+    }
+
+    pub fn append(&mut self, seq: u64, event: &Event) -> io::Result<()> {
+        let record = RecordOut { seq, event };
+        let mut line = serde_json::to_string(&record)?;
+        line.push('\n');
+        self.file.write_all(line.as_bytes())?;
+        self.file.sync_data()
+    }
+
+    // Called once a snapshot has been written: everything up to now is in it.
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.sync_data()
+    }
+}
+
+pub struct Recovery {
+    pub replayed: usize,
+    pub truncated: bool,
+}
+
+pub fn apply(warehouse: &mut Warehouse, event: Event) -> Result<(), WarehouseError> {
+    match event {
+        Event::ItemStored {
+            row,
+            shelf,
+            zone,
+            item,
+        } => warehouse.add_zone(item, row, shelf, zone),
+        Event::ItemRemoved { row, shelf, zone } => {
+            warehouse.remove_zone(row, shelf, zone).map(|_| ())
+        }
+        Event::Restocked {
+            row,
+            shelf,
+            zone,
+            quantity,
+        } => warehouse.add_quantity(row, shelf, zone, quantity),
+        Event::Picked {
+            row,
+            shelf,
+            zone,
+            quantity,
+        } => warehouse.take_quantity(row, shelf, zone, quantity),
+        Event::ItemMoved { from, to } => warehouse.move_item(from, to),
+        Event::Quarantined { row, shelf, zone } => warehouse.quarantine(row, shelf, zone),
+        Event::ReceiptSaved { receipt } => {
+            warehouse.put_receipt(receipt);
+            Ok(())
+        }
+        Event::OrderSaved { order } => {
+            warehouse.put_order(order);
+            Ok(())
+        }
+    }
+}
+
+// Replays every record newer than the warehouse's last sequence number. A
+// last record that is cut short (crash mid-write) is dropped from the file.
+pub fn replay(path: &Path, warehouse: &mut Warehouse) -> Result<Recovery, JournalError> {
+    let bytes = match std::fs::read(path) {
+        Ok(bytes) => bytes,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(Recovery {
+                replayed: 0,
+                truncated: false,
+            })
+        }
+        Err(e) => return Err(JournalError::Io(e)),
+    };
+
+    let mut replayed = 0;
+    let mut valid_len = 0;
+    let mut truncated = false;
+    let lines: Vec<&[u8]> = bytes.split_inclusive(|b| *b == b'\n').collect();
+
+    for (index, raw) in lines.iter().enumerate() {
+        let is_last = index + 1 == lines.len();
+        let parsed = std::str::from_utf8(raw)
+            .map_err(|e| e.to_string())
+            .and_then(|text| serde_json::from_str::<RecordIn>(text).map_err(|e| e.to_string()));
+
+        let record = match parsed {
+            Ok(record) if raw.ends_with(b"\n") => record,
+            Err(reason) if !is_last => {
+                return Err(JournalError::Corrupt {
+                    line: index + 1,
+                    reason,
+                })
+            }
+            _ => {
+                truncated = true;
+                break;
+            }
+        };
+
+        valid_len += raw.len();
+        if record.seq <= warehouse.event_seq() {
+            continue;
+        }
+        apply(warehouse, record.event).map_err(|e| JournalError::Corrupt {
+            line: index + 1,
+            reason: e.to_string(),
+        })?;
+        warehouse.set_event_seq(record.seq);
+        replayed += 1;
+    }
+
+    if truncated {
+        let file = OpenOptions::new().write(true).open(path)?;
+        file.set_len(valid_len as u64)?;
+    }
+
+    Ok(Recovery {
+        replayed,
+        truncated,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::Quality;
+    use crate::layout::Layout;
+    use crate::strategy::Closest;
+    use chrono::DateTime;
+    use std::path::PathBuf;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("warehouse_{}_{}.journal", name, std::process::id()))
+    }
+
+    fn small_warehouse() -> Warehouse {
+        Warehouse::from_layout(&Layout::parse("row 3").unwrap(), Box::new(Closest))
+    }
+
+    fn item(id: u32) -> Item {
+        Item::new(
+            id,
+            format!("Item {}", id),
+            1,
+            Quality::Normal,
+            DateTime::UNIX_EPOCH,
+        )
+    }
+
+    #[test]
+    fn test_replay_rebuilds_warehouse() {
+        let path = temp_path("rebuild");
+        let _ = std::fs::remove_file(&path);
+
+        let mut original = small_warehouse();
+        original.attach_journal(Journal::open(&path).unwrap());
+        original.add_zone(item(1), 0, 0, 0).unwrap();
+        original.add_zone(item(2), 0, 0, 1).unwrap();
+        original.remove_zone(0, 0, 0).unwrap();
+        original
+            .move_item(Location::new(0, 0, 1), Location::new(0, 0, 2))
+            .unwrap();
+        original.quarantine(0, 0, 2).unwrap();
+        let order = original.create_order(&[(2, 1)]).unwrap();
+        original.cancel_order(order).unwrap();
+
+        let mut rebuilt = small_warehouse();
+        let recovery = replay(&path, &mut rebuilt).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(recovery.replayed, 7);
+        assert!(!recovery.truncated);
+        let zones = &rebuilt.rows[0].shelves[0].zones;
+        assert!(zones[0].item.is_none() && zones[1].item.is_none());
+        assert_eq!(zones[2].item.as_ref().unwrap().id, 2);
+        assert!(zones[2].item.as_ref().unwrap().quarantined);
+        assert_eq!(rebuilt.find_by_id(2), vec![Location::new(0, 0, 2)]);
+        assert_eq!(
+            rebuilt.outbound_order(order).unwrap().status,
+            crate::orders::OrderStatus::Cancelled
+        );
+    }
+
+    #[test]
+    fn test_replay_drops_truncated_last_record() {
+        let path = temp_path("truncated");
+        let _ = std::fs::remove_file(&path);
+
+        let mut journal = Journal::open(&path).unwrap();
+        let event = Event::ItemStored {
+            row: 0,
+            shelf: 0,
+            zone: 2,
+            item: item(5),
+        };
+        journal.append(1, &event).unwrap();
+        drop(journal);
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"{\"seq\":2,\"event\":{\"ItemRem").unwrap();
+
+        let mut warehouse = small_warehouse();
+        let recovery = replay(&path, &mut warehouse).unwrap();
+        let left = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(recovery.replayed, 1);
+        assert!(recovery.truncated);
+        assert!(left.ends_with('\n'));
+        assert_eq!(
+            warehouse.rows[0].shelves[0].zones[2]
+                .item
+                .as_ref()
+                .unwrap()
+                .id,
+            5
+        );
+    }
+
+    #[test]
+    fn test_replay_skips_events_already_in_snapshot() {
+        let path = temp_path("skip");
+        let _ = std::fs::remove_file(&path);
+
+        let mut warehouse = small_warehouse();
+        warehouse.attach_journal(Journal::open(&path).unwrap());
+        warehouse.add_zone(item(1), 0, 0, 0).unwrap();
+        warehouse.add_zone(item(2), 0, 0, 1).unwrap();
+
+        let mut snapshot = small_warehouse();
+        snapshot.add_zone(item(1), 0, 0, 0).unwrap();
+        snapshot.set_event_seq(1);
+        let recovery = replay(&path, &mut snapshot).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(recovery.replayed, 1);
+        assert_eq!(snapshot.event_seq(), 2);
+    }
+
+    #[test]
+    fn test_replay_rejects_corrupt_middle_record() {
+        let path = temp_path("corrupt");
+        std::fs::write(&path, "garbage\n{\"seq\":1}\n").unwrap();
+
+        let mut warehouse = small_warehouse();
+        let result = replay(&path, &mut warehouse);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(JournalError::Corrupt { line: 1, .. })));
+    }
+}
@@ -0,0 +1,287 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       layout.rs
+ * Purpose:    Describes the rows, shelves and zones of a warehouse and
+ *             reads that description from a layout file.
+ * =======================================================================
+ */
+
+use crate::route::{Aisles, DistanceModel, RouteStyle};
+use crate::warehouse::DEFAULT_ZONE_CAPACITY;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const DEFAULT_LAYOUT_PATH: &str = "layout.txt";
+
+/*
+ * Layout file format, one statement per line ('#' starts a comment):
+ *
+ *   grid 2 2 3     2 rows, each with 2 shelves of 3 zones
+ *   row 3 3 2      one row with three shelves of 3, 3 and 2 zones
+ *   capacity 50    units of one item each zone can hold
+ *   walk 5 1 1     steps to cross a row, between shelves, between zones
+ *   aisles ends    rows are left at their ends (ends) or straight across (open)
+ *   route nearest  how pick paths are ordered: serpentine, nearest, shortest
+ *
+ * Statements are added in order, so grids and rows can be mixed.
+ */
+#[derive(Debug, PartialEq)]
+pub struct Layout {
+    // For every row, the number of zones on each of its shelves.
+    pub rows: Vec<Vec<usize>>,
+    pub zone_capacity: u32,
+    pub distance: DistanceModel,
+    pub route: RouteStyle,
+}
+
+#[derive(Debug)]
+pub enum LayoutError {
+    Io(io::Error),
+    Empty,
+    Malformed { line: usize, reason: String },
+}
+
+impl fmt::Display for LayoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LayoutError::Io(e) => write!(f, "could not read layout file: {}", e),
+            LayoutError::Empty => write!(f, "layout does not define any rows"),
+            LayoutError::Malformed { line, reason } => {
+                write!(f, "layout line {}: {}", line, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for LayoutError {}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            rows: vec![vec![3; 2]; 2],
+            zone_capacity: DEFAULT_ZONE_CAPACITY,
+            distance: DistanceModel::default(),
+            route: RouteStyle::default(),
+        }
+    }
+}
+
+impl Layout {
+    pub fn parse(text: &str) -> Result<Layout, LayoutError> {
+        let mut rows = vec![];
+        let mut zone_capacity = DEFAULT_ZONE_CAPACITY;
+        let mut distance = DistanceModel::default();
+        let mut route = RouteStyle::default();
+
+        for (index, raw) in text.lines().enumerate() {
+            let line = index + 1;
+            let content = raw.split('#').next().unwrap_or("").trim();
+            if content.is_empty() {
+                continue;
+            }
+
+            let mut words = content.split_whitespace();
+            let keyword = words.next().unwrap_or("");
+            let malformed = |reason: String| LayoutError::Malformed { line, reason };
+
+            // The statements that take a word rather than numbers.
+            let args: Vec<&str> = words.clone().collect();
+            match (keyword, args.as_slice()) {
+                ("aisles", ["ends"]) => distance.aisles = Aisles::Ends,
+                ("aisles", ["open"]) => distance.aisles = Aisles::Open,
+                ("aisles", _) => {
+                    return Err(malformed("aisles expects: aisles ends|open".to_string()))
+                }
+                ("route", [style]) => route = RouteStyle::parse(style).map_err(malformed)?,
+                ("route", _) => {
+                    return Err(malformed(
+                        "route expects: route serpentine|nearest|shortest".to_string(),
+                    ))
+                }
+                _ => (),
+            }
+            if matches!(keyword, "aisles" | "route") {
+                continue;
+            }
+
+            let numbers = words
+                .map(|word| {
+                    word.parse::<usize>().map_err(|_| LayoutError::Malformed {
+                        line,
+                        reason: format!("'{}' is not a number", word),
+                    })
+                })
+                .collect::<Result<Vec<usize>, LayoutError>>()?;
+
+            if keyword != "walk" && numbers.contains(&0) {
+                return Err(LayoutError::Malformed {
+                    line,
+                    reason: "counts must be at least 1".to_string(),
+                });
+            }
+
+            match (keyword, numbers.as_slice()) {
+                ("row", []) => {
+                    return Err(LayoutError::Malformed {
+                        line,
+                        reason: "a row needs at least one shelf".to_string(),
+                    })
+                }
+                ("row", shelves) => rows.push(shelves.to_vec()),
+                ("grid", [row_count, shelf_count, zone_count]) => {
+                    for _ in 0..*row_count {
+                        rows.push(vec![*zone_count; *shelf_count]);
+                    }
+                }
+                ("capacity", [capacity]) => {
+                    zone_capacity =
+                        u32::try_from(*capacity).map_err(|_| LayoutError::Malformed {
+                            line,
+                            reason: format!("capacity {} is too large", capacity),
+                        })?;
+                }
+                ("walk", [row, shelf, zone]) => {
+                    distance.row = *row as u64;
+                    distance.shelf = *shelf as u64;
+                    distance.zone = *zone as u64;
+                }
+                ("walk", _) => {
+                    return Err(LayoutError::Malformed {
+                        line,
+                        reason: "walk expects: walk <row> <shelf> <zone>".to_string(),
+                    })
+                }
+                ("capacity", _) => {
+                    return Err(LayoutError::Malformed {
+                        line,
+                        reason: "capacity expects: capacity <units>".to_string(),
+                    })
+                }
+                ("grid", _) => {
+                    return Err(LayoutError::Malformed {
+                        line,
+                        reason: "grid expects: grid <rows> <shelves> <zones>".to_string(),
+                    })
+                }
+                (other, _) => {
+                    return Err(LayoutError::Malformed {
+                        line,
+                        reason: format!("unknown statement '{}'", other),
+                    })
+                }
+            }
+        }
+
+        if rows.is_empty() {
+            return Err(LayoutError::Empty);
+        }
+
+        Ok(Layout {
+            rows,
+            zone_capacity,
+            distance,
+            route,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Layout, LayoutError> {
+        let text = fs::read_to_string(path).map_err(LayoutError::Io)?;
+        Layout::parse(&text)
+    }
+
+    // Uses the given file, else layout.txt when present, else the default grid.
+    pub fn resolve(path: Option<&Path>) -> Result<Layout, LayoutError> {
+        match path {
+            Some(path) => Layout::load(path),
+            None if Path::new(DEFAULT_LAYOUT_PATH).exists() => {
+                Layout::load(Path::new(DEFAULT_LAYOUT_PATH))
+            }
+            None => Ok(Layout::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_grid_and_irregular_rows() {
+        let text = "# site B\ngrid 1 2 3\n\nrow 4 1   # short shelf\n";
+        let layout = Layout::parse(text).unwrap();
+        assert_eq!(layout.rows, vec![vec![3, 3], vec![4, 1]]);
+        assert_eq!(layout.zone_capacity, DEFAULT_ZONE_CAPACITY);
+        assert_eq!(Layout::parse("capacity 8\nrow 1").unwrap().zone_capacity, 8);
+    }
+
+    #[test]
+    fn test_parse_walking_model() {
+        let layout = Layout::parse("row 3\nwalk 8 0 2\naisles open\nroute nearest").unwrap();
+        assert_eq!(
+            layout.distance,
+            DistanceModel {
+                row: 8,
+                shelf: 0,
+                zone: 2,
+                aisles: Aisles::Open
+            }
+        );
+        assert_eq!(layout.route, RouteStyle::Nearest);
+        assert_eq!(
+            Layout::parse("row 3").unwrap().route,
+            RouteStyle::Serpentine
+        );
+        assert!(matches!(
+            Layout::parse("row 3\nroute zigzag"),
+            Err(LayoutError::Malformed { line: 2, .. })
+        ));
+        assert!(matches!(
+            Layout::parse("row 3\naisles"),
+            Err(LayoutError::Malformed { line: 2, .. })
+        ));
+        assert!(matches!(
+            Layout::parse("walk 1 2"),
+            Err(LayoutError::Malformed { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn test_default_is_two_by_two_by_three() {
+        assert_eq!(Layout::parse("grid 2 2 3").unwrap(), Layout::default());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(matches!(
+            Layout::parse("# nothing\n"),
+            Err(LayoutError::Empty)
+        ));
+        assert!(matches!(
+            Layout::parse("row 3\nrow\n"),
+            Err(LayoutError::Malformed { line: 2, .. })
+        ));
+        assert!(matches!(
+            Layout::parse("row 3 x"),
+            Err(LayoutError::Malformed { line: 1, .. })
+        ));
+        assert!(matches!(
+            Layout::parse("grid 2 0 3"),
+            Err(LayoutError::Malformed { line: 1, .. })
+        ));
+        assert!(matches!(
+            Layout::parse("grid 2 2"),
+            Err(LayoutError::Malformed { line: 1, .. })
+        ));
+        assert!(matches!(
+            Layout::parse("row 1\ncapacity"),
+            Err(LayoutError::Malformed { line: 2, .. })
+        ));
+        assert!(matches!(
+            Layout::parse("aisle 2"),
+            Err(LayoutError::Malformed { line: 1, .. })
+        ));
+    }
+}
@@ -0,0 +1,32 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       lib.rs
+ * Purpose:    The warehouse library: items, filters, placement strategies,
+ *             storage and reports. The menu and the command line in
+ *             main.rs are built on top of it.
+ * =======================================================================
+ */
+
+pub mod clock;
+pub mod csv;
+pub mod filter;
+mod index;
+pub mod item;
+pub mod journal;
+pub mod layout;
+pub mod network;
+pub mod orders;
+pub mod report;
+pub mod route;
+pub mod rules;
+pub mod storage;
+pub mod strategy;
+pub mod warehouse;
+
+#[cfg(test)]
+mod test_support;
+
+pub use crate::filter::{Filter, FilterResult, Rejection};
+pub use crate::item::{Item, Quality};
+pub use crate::warehouse::{Location, Placement, Warehouse, WarehouseError};
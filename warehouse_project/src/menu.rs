@@ -0,0 +1,444 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       menu.rs
+ * Purpose:    The interactive menu, reading and writing through a Console
+ *             so it can be driven by a person, a script file or a test.
+ * =======================================================================
+ */
+
+use crate::cli::{self, Settings};
+use crate::utils::{parse_date_input, parse_input, print_lines, read_input, Console, Eof};
+use warehouse::filter::Filter;
+use warehouse::item::{Item, Quality};
+use warehouse::strategy::StrategySpec;
+use warehouse::warehouse::{Location, PickOrder, Warehouse, DEFAULT_EXPIRY_DAYS};
+use warehouse::{csv, report, storage};
+
+// Runs the menu until Exit is chosen or the input ends, then saves the
+// warehouse. Returns the exit code.
+pub fn run(console: &mut dyn Console, settings: &Settings) -> i32 {
+    console.write_line("Welcome Mr.Inventory Manager");
+
+    let snapshot_path = settings.file.as_path();
+    let filters = match cli::load_rules(settings) {
+        Ok(filters) => filters,
+        Err(msg) => {
+            console.write_line(&format!("Error: {}", msg));
+            return cli::EXIT_FAILURE;
+        }
+    };
+    let mut warehouse = match cli::open_warehouse(settings) {
+        Ok((warehouse, recovery)) => {
+            if recovery.replayed > 0 {
+                console.write_line(&format!(
+                    "Recovered {} operations from the journal",
+                    recovery.replayed
+                ));
+            }
+            if recovery.truncated {
+                console.write_line("Discarded an incomplete last journal record");
+            }
+            warehouse
+        }
+        Err(msg) => {
+            console.write_line(&format!("Error: {}", msg));
+            return cli::EXIT_FAILURE;
+        }
+    };
+
+    loop {
+        console.write_line("\n===== INVENTORY MANAGER =====");
+        console.write_line("1. Add new item");
+        console.write_line("2. Search item by ID");
+        console.write_line("3. Search item by name");
+        console.write_line("4. Find all item locations, in walking order");
+        console.write_line("5. Remove item from zone");
+        console.write_line("6. Show all items");
+        console.write_line("7. Show items close to expiry");
+        console.write_line("8. Exit");
+        console.write_line("9. Change allocation strategy");
+        console.write_line("10. Pick units of an item");
+        console.write_line("11. Move an item");
+        console.write_line("12. Compact shelves");
+        console.write_line("13. Quarantine expired items");
+        console.write_line("14. Show occupancy report");
+        console.write_line("15. Import items from CSV");
+        console.write_line("16. Export items to CSV");
+        console.write_line("==================================");
+
+        // At the end of the input the last prompt is left unanswered.
+        let Ok(option) = read_input(console, "Choose an option: ") else {
+            console.write_line("");
+            break;
+        };
+
+        let result = match option.as_str() {
+            "1" => add_item(console, &mut warehouse, &filters),
+            "2" => search_by_id(console, &warehouse),
+            "3" => search_by_name(console, &warehouse),
+            "4" => find_locations(console, &warehouse),
+            "5" => remove_item(console, &mut warehouse),
+            "6" => show_all(console, &warehouse),
+            "7" => show_near_expiry(console, &warehouse),
+            "8" => break,
+            "9" => change_strategy(console, &mut warehouse),
+            "10" => pick_item(console, &mut warehouse),
+            "11" => move_item(console, &mut warehouse),
+            "12" => compact(console, &mut warehouse),
+            "13" => quarantine_expired(console, &mut warehouse),
+            "14" => show_occupancy(console, &warehouse),
+            "15" => import_csv(console, &mut warehouse, &filters),
+            "16" => export_csv(console, &warehouse),
+            _ => {
+                console.write_line("Sorry, Invalid choice. Please try again.");
+                Ok(())
+            }
+        };
+        if result == Err(Eof) {
+            console.write_line("");
+            break;
+        }
+    }
+
+    match storage::checkpoint(&mut warehouse, snapshot_path) {
+        Ok(()) => console.write_line(&format!("Warehouse saved to {}", snapshot_path.display())),
+        Err(e) => console.write_line(&format!("Error saving {}: {}", snapshot_path.display(), e)),
+    }
+    console.write_line("Leaving...\nSee you soon");
+    cli::EXIT_OK
+}
+
+fn add_item(
+    console: &mut dyn Console,
+    warehouse: &mut Warehouse,
+    filters: &[Box<dyn Filter>],
+) -> Result<(), Eof> {
+    let id = parse_input(console, "Enter item ID: ")? as u32;
+    let name = read_input(console, "Enter item name: ")?;
+    let quantity = parse_input(console, "Enter quantity: ")? as u32;
+
+    console.write_line("Choose item quality:");
+    console.write_line("1. Normal");
+    console.write_line("2. Fragile");
+    console.write_line("3. Oversized");
+    let quality_choice = read_input(console, "Your choice: ")?;
+    let mut lot = String::new();
+
+    let quality = match quality_choice.as_str() {
+        "1" => Quality::Normal,
+        "2" => {
+            let expiry = parse_date_input(console, "Enter expiry date (YYYY-MM-DD): ")?;
+            let max_shelf = parse_input(console, "Enter max row number for fragile item: ")? as u32;
+            lot = read_input(console, "Enter lot number (empty for none): ")?;
+            Quality::Fragile {
+                expiry_date: expiry,
+                max_shelf,
+            }
+        }
+        "3" => {
+            let zones = parse_input(console, "Enter number of zones needed: ")? as u32;
+            Quality::Oversized {
+                zones_needed: zones,
+            }
+        }
+        _ => {
+            console.write_line("Invalid quality selected, defaulting to Normal.");
+            Quality::Normal
+        }
+    };
+
+    let mut item = Item::new(id, name, quantity, quality, warehouse.now());
+    if !lot.is_empty() {
+        item = item.with_lot(&lot);
+    }
+
+    match warehouse.store(item, filters) {
+        Ok(placements) => {
+            for placement in placements {
+                console.write_line(&format!("Item stored at {}", placement));
+            }
+        }
+        Err(e) => console.write_line(&e.to_string()),
+    }
+    Ok(())
+}
+
+fn search_by_id(console: &mut dyn Console, warehouse: &Warehouse) -> Result<(), Eof> {
+    let id = parse_input(console, "Enter ID to search: ")? as u32;
+    print_lines(console, &report::search_by_id(warehouse, id));
+    Ok(())
+}
+
+fn search_by_name(console: &mut dyn Console, warehouse: &Warehouse) -> Result<(), Eof> {
+    let name = read_input(console, "Enter name to search: ")?;
+    print_lines(console, &report::search_by_name(warehouse, &name));
+    Ok(())
+}
+
+fn find_locations(console: &mut dyn Console, warehouse: &Warehouse) -> Result<(), Eof> {
+    let id = parse_input(console, "Enter item ID: ")? as u32;
+
+    let route = warehouse.route(&warehouse.find_by_id(id));
+    for loc in &route.stops {
+        console.write_line(&loc.to_string());
+    }
+    console.write_line(&format!("Walking distance: {} steps", route.distance));
+    Ok(())
+}
+
+fn remove_item(console: &mut dyn Console, warehouse: &mut Warehouse) -> Result<(), Eof> {
+    let row = parse_input(console, "Enter row: ")?;
+    let shelf = parse_input(console, "Enter shelf: ")?;
+    let zone = parse_input(console, "Enter zone: ")?;
+
+    match warehouse.remove_zone(row, shelf, zone) {
+        Ok(_) => console.write_line(&format!(
+            "Item removed from Row {}, Shelf {}, Zone {}",
+            row, shelf, zone
+        )),
+        Err(e) => console.write_line(&format!("Sorry, {}", e)),
+    }
+    Ok(())
+}
+
+fn show_all(console: &mut dyn Console, warehouse: &Warehouse) -> Result<(), Eof> {
+    print_lines(console, &report::all_items(warehouse));
+    Ok(())
+}
+
+fn show_near_expiry(console: &mut dyn Console, warehouse: &Warehouse) -> Result<(), Eof> {
+    let prompt = format!("Days ahead (default {}): ", DEFAULT_EXPIRY_DAYS);
+    let days = match read_input(console, &prompt)?.as_str() {
+        "" => DEFAULT_EXPIRY_DAYS,
+        text => match text.parse::<i64>() {
+            Ok(days) => days,
+            Err(_) => {
+                console.write_line(&format!("Sorry, '{}' is not a number of days.", text));
+                return Ok(());
+            }
+        },
+    };
+
+    print_lines(console, &report::near_expiry(warehouse, days));
+    Ok(())
+}
+
+fn change_strategy(console: &mut dyn Console, warehouse: &mut Warehouse) -> Result<(), Eof> {
+    console.write_line(&format!("Current strategy: {}", warehouse.strategy_spec()));
+    console.write_line("Available: closest, robin, emptiest, group, random[:SEED]");
+    let choice = read_input(console, "New strategy: ")?;
+
+    match StrategySpec::parse(&choice) {
+        Ok(spec) => {
+            warehouse.set_strategy(spec.build());
+            console.write_line(&format!("Allocation strategy set to {}", spec));
+        }
+        Err(e) => console.write_line(&format!("Sorry, {}.", e)),
+    }
+    Ok(())
+}
+
+fn pick_item(console: &mut dyn Console, warehouse: &mut Warehouse) -> Result<(), Eof> {
+    let id = parse_input(console, "Enter item ID: ")? as u32;
+    let quantity = parse_input(console, "Enter quantity to pick: ")? as u32;
+    let order = match read_input(console, "Pick order (fifo/fefo): ")?.as_str() {
+        "" => PickOrder::Fifo,
+        text => match PickOrder::parse(text) {
+            Ok(order) => order,
+            Err(e) => {
+                console.write_line(&format!("Sorry, {}.", e));
+                return Ok(());
+            }
+        },
+    };
+
+    match warehouse.pick(id, quantity, order) {
+        Ok(picks) => {
+            for pick in picks {
+                console.write_line(&format!("Picked from {}", pick));
+            }
+        }
+        Err(e) => console.write_line(&e.to_string()),
+    }
+    Ok(())
+}
+
+fn move_item(console: &mut dyn Console, warehouse: &mut Warehouse) -> Result<(), Eof> {
+    let from = Location::new(
+        parse_input(console, "Enter row: ")?,
+        parse_input(console, "Enter shelf: ")?,
+        parse_input(console, "Enter zone: ")?,
+    );
+    let to = Location::new(
+        parse_input(console, "Enter new row: ")?,
+        parse_input(console, "Enter new shelf: ")?,
+        parse_input(console, "Enter new zone: ")?,
+    );
+
+    match warehouse.move_item(from, to) {
+        Ok(()) => console.write_line(&format!("Item moved from {} to {}", from, to)),
+        Err(e) => console.write_line(&format!("Sorry, {}", e)),
+    }
+    Ok(())
+}
+
+fn compact(console: &mut dyn Console, warehouse: &mut Warehouse) -> Result<(), Eof> {
+    let plan = warehouse.compaction_plan();
+    if plan.moves.is_empty() {
+        console.write_line("The shelves are already compact.");
+        return Ok(());
+    }
+
+    for step in &plan.moves {
+        console.write_line(&step.to_string());
+    }
+    let answer = read_input(console, "Apply these moves? (y/n): ")?;
+    if answer.eq_ignore_ascii_case("y") {
+        match warehouse.apply_plan(&plan) {
+            Ok(()) => console.write_line(&format!("{} moves applied.", plan.moves.len())),
+            Err(e) => console.write_line(&format!("Sorry, {}", e)),
+        }
+    }
+    Ok(())
+}
+
+fn quarantine_expired(console: &mut dyn Console, warehouse: &mut Warehouse) -> Result<(), Eof> {
+    match warehouse.quarantine_expired() {
+        Ok(quarantined) => {
+            for loc in &quarantined {
+                console.write_line(&format!("Quarantined {}", loc));
+            }
+            console.write_line(&format!("Items quarantined: {}", quarantined.len()));
+        }
+        Err(e) => console.write_line(&format!("Sorry, {}", e)),
+    }
+    Ok(())
+}
+
+fn show_occupancy(console: &mut dyn Console, warehouse: &Warehouse) -> Result<(), Eof> {
+    print_lines(console, &report::occupancy_report(warehouse));
+    Ok(())
+}
+
+fn import_csv(
+    console: &mut dyn Console,
+    warehouse: &mut Warehouse,
+    filters: &[Box<dyn Filter>],
+) -> Result<(), Eof> {
+    let path = read_input(console, "CSV file to import: ")?;
+
+    match std::fs::read_to_string(&path) {
+        Ok(text) => {
+            let reports = csv::import(warehouse, &text, filters);
+            print_lines(console, &report::import_report(&reports));
+        }
+        Err(e) => console.write_line(&format!("Sorry, could not read {}: {}", path, e)),
+    }
+    Ok(())
+}
+
+fn export_csv(console: &mut dyn Console, warehouse: &Warehouse) -> Result<(), Eof> {
+    let path = read_input(console, "CSV file to write: ")?;
+
+    match std::fs::write(&path, csv::export(warehouse)) {
+        Ok(()) => console.write_line(&format!("Items written to {}", path)),
+        Err(e) => console.write_line(&format!("Sorry, could not write {}: {}", path, e)),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::IoConsole;
+
+    // Replays the typed lines against a fresh warehouse file and returns
+    // everything the menu printed.
+    fn session(name: &str, script: &str) -> String {
+        let file = std::env::temp_dir().join(format!(
+            "warehouse_menu_{}_{}.json",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&file);
+        let (settings, _) = cli::parse_settings(&[
+            "--file".to_string(),
+            file.to_str().unwrap().to_string(),
+            "--today".to_string(),
+            "2030-01-10".to_string(),
+        ])
+        .unwrap();
+
+        let mut output = Vec::new();
+        let code = run(
+            &mut IoConsole::new(script.as_bytes(), &mut output),
+            &settings,
+        );
+        assert_eq!(code, cli::EXIT_OK);
+
+        let _ = std::fs::remove_file(&file);
+        let _ = std::fs::remove_file(storage::journal_path(&file));
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_session_adds_searches_and_exits() {
+        let output = session(
+            "exit",
+            "1\n7\nBolts\nten\n10\n1\n2\n7\n4\n7\n10\n7\n4\n\n8\n",
+        );
+
+        assert!(output.starts_with("Welcome Mr.Inventory Manager\n"));
+        assert!(output.contains("Please enter a valid number."));
+        assert!(output.contains("Item stored at Row 0, Shelf 0, Zone 0 (10 units)"));
+        assert!(output.contains("Total for ID 7: 10 units in 1 zones"));
+        assert!(output.contains("Row 0, Shelf 0, Zone 0\nWalking distance: 0 steps"));
+        assert!(output.contains("Picked from Row 0, Shelf 0, Zone 0 (4 units)"));
+        assert!(output.ends_with("Leaving...\nSee you soon\n"));
+    }
+
+    #[test]
+    fn test_session_ends_cleanly_on_eof() {
+        // The input runs out in the middle of adding an item.
+        let output = session("eof", "99\n1\n3\nGlue\n");
+
+        assert!(output.contains("Sorry, Invalid choice. Please try again."));
+        assert!(output.contains("Enter quantity: "));
+        assert!(!output.contains("Item stored"));
+        assert!(output.contains("Warehouse saved to"));
+        assert!(output.ends_with("Leaving...\nSee you soon\n"));
+    }
+
+    #[test]
+    fn test_load_errors_go_to_the_console() {
+        let file = std::env::temp_dir().join(format!(
+            "warehouse_menu_corrupt_{}.json",
+            std::process::id()
+        ));
+        std::fs::write(&file, "not a warehouse").unwrap();
+        let (settings, _) =
+            cli::parse_settings(&["--file".to_string(), file.to_str().unwrap().to_string()])
+                .unwrap();
+
+        let mut output = Vec::new();
+        let code = run(
+            &mut IoConsole::new("8\n".as_bytes(), &mut output),
+            &settings,
+        );
+        std::fs::remove_file(&file).unwrap();
+        let _ = std::fs::remove_file(storage::journal_path(&file));
+
+        assert_eq!(code, cli::EXIT_FAILURE);
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("Error: could not load"));
+        assert!(!output.contains("INVENTORY MANAGER"));
+    }
+
+    #[test]
+    fn test_empty_script_only_saves() {
+        let output = session("empty", "");
+        assert_eq!(output.matches("INVENTORY MANAGER").count(), 1);
+        assert!(output.ends_with("Leaving...\nSee you soon\n"));
+    }
+}
@@ -0,0 +1,437 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       network.rs
+ * Purpose:    Several named sites, each a warehouse of its own: stock
+ *             queries across all of them and transfers of goods from a
+ *             zone at one site to another site.
+ * =======================================================================
+ */
+
+use crate::filter::Filter;
+use crate::item::Item;
+use crate::warehouse::{Location, Lot, Placement, Warehouse, WarehouseError};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Send `quantity` units of what is stored at `location` in site `from` to
+// site `to`, which places them with its own strategy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    pub from: String,
+    pub location: Location,
+    pub quantity: u32,
+    pub to: String,
+}
+
+impl fmt::Display for Transfer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} units from {} at {} to {}",
+            self.quantity, self.from, self.location, self.to
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum NetworkError {
+    // No site goes by this name.
+    UnknownSite(String),
+    // A site with this name is already in the network.
+    DuplicateSite(String),
+    // A transfer must go to another site.
+    SameSite(String),
+    // Quarantined stock stays where it is.
+    Quarantined { site: String, location: Location },
+    // Oversized goods are sent whole, never in part.
+    Unsplittable { site: String, location: Location },
+    // The sending site could not give up the units.
+    Source { site: String, error: WarehouseError },
+    // The receiving site could not take the units; nothing was moved.
+    Destination { site: String, error: WarehouseError },
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::UnknownSite(site) => write!(f, "There is no site named '{}'.", site),
+            NetworkError::DuplicateSite(site) => {
+                write!(f, "A site named '{}' already exists.", site)
+            }
+            NetworkError::SameSite(site) => {
+                write!(f, "Cannot transfer from '{}' to itself.", site)
+            }
+            NetworkError::Quarantined { site, location } => write!(
+                f,
+                "The item at {} in '{}' is quarantined and cannot be sent.",
+                location, site
+            ),
+            NetworkError::Unsplittable { site, location } => write!(
+                f,
+                "The oversized item at {} in '{}' can only be sent whole.",
+                location, site
+            ),
+            NetworkError::Source { site, error } => write!(f, "At '{}': {}", site, error),
+            NetworkError::Destination { site, error } => {
+                write!(f, "At '{}': {} Nothing was transferred.", site, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {}
+
+// The warehouses of every site, by name.
+#[derive(Default)]
+pub struct Network {
+    sites: BTreeMap<String, Warehouse>,
+}
+
+impl Network {
+    pub fn new() -> Network {
+        Network::default()
+    }
+
+    pub fn add_site(&mut self, name: &str, warehouse: Warehouse) -> Result<(), NetworkError> {
+        if self.sites.contains_key(name) {
+            return Err(NetworkError::DuplicateSite(name.to_string()));
+        }
+        self.sites.insert(name.to_string(), warehouse);
+        Ok(())
+    }
+
+    pub fn site(&self, name: &str) -> Result<&Warehouse, NetworkError> {
+        self.sites
+            .get(name)
+            .ok_or_else(|| NetworkError::UnknownSite(name.to_string()))
+    }
+
+    pub fn site_mut(&mut self, name: &str) -> Result<&mut Warehouse, NetworkError> {
+        self.sites
+            .get_mut(name)
+            .ok_or_else(|| NetworkError::UnknownSite(name.to_string()))
+    }
+
+    // Every site with its warehouse, by name.
+    pub fn sites(&self) -> impl Iterator<Item = (&str, &Warehouse)> {
+        self.sites
+            .iter()
+            .map(|(name, warehouse)| (name.as_str(), warehouse))
+    }
+
+    pub fn sites_mut(&mut self) -> impl Iterator<Item = (&str, &mut Warehouse)> {
+        self.sites
+            .iter_mut()
+            .map(|(name, warehouse)| (name.as_str(), warehouse))
+    }
+
+    // The lots of the item with this id at every site holding it, by site.
+    pub fn lots(&self, id: u32) -> Vec<(&str, Vec<Lot>)> {
+        self.sites()
+            .map(|(name, warehouse)| (name, warehouse.lots(id)))
+            .filter(|(_, lots)| !lots.is_empty())
+            .collect()
+    }
+
+    // Zones at every site holding the item with this id.
+    pub fn find_by_id(&self, id: u32) -> Vec<(&str, Location)> {
+        self.sites()
+            .flat_map(|(name, warehouse)| {
+                warehouse
+                    .find_by_id(id)
+                    .into_iter()
+                    .map(move |loc| (name, loc))
+            })
+            .collect()
+    }
+
+    // Zones at every site holding items with this name, ignoring case.
+    pub fn find_by_name(&self, name: &str) -> Vec<(&str, Location)> {
+        self.sites()
+            .flat_map(|(site, warehouse)| {
+                warehouse
+                    .find_by_name(name)
+                    .into_iter()
+                    .map(move |loc| (site, loc))
+            })
+            .collect()
+    }
+
+    // Units of the item with this id across all sites.
+    pub fn total(&self, id: u32) -> u64 {
+        self.lots(id)
+            .iter()
+            .flat_map(|(_, lots)| lots)
+            .map(|lot| lot.units)
+            .sum()
+    }
+
+    // Carries out the transfer and returns where the destination put the
+    // units. The units keep their lot and receipt time. They pass the
+    // filters and the destination's own checks like a new delivery, and
+    // the source is only touched once the destination has taken them all.
+    // On failure both sites are left as they were.
+    pub fn transfer(
+        &mut self,
+        order: &Transfer,
+        filters: &[Box<dyn Filter>],
+    ) -> Result<Vec<Placement>, NetworkError> {
+        if order.from == order.to {
+            return Err(NetworkError::SameSite(order.from.clone()));
+        }
+        self.site(&order.to)?;
+        let source_error = |error| NetworkError::Source {
+            site: order.from.clone(),
+            error,
+        };
+
+        let source = self.site(&order.from)?;
+        let (head, item) = stored_at(source, order.location).map_err(source_error)?;
+        if item.quarantined {
+            return Err(NetworkError::Quarantined {
+                site: order.from.clone(),
+                location: head,
+            });
+        }
+        let whole = item.zones_needed() > 1;
+        if whole && order.quantity != item.quantity {
+            return Err(NetworkError::Unsplittable {
+                site: order.from.clone(),
+                location: head,
+            });
+        }
+        if order.quantity == 0 {
+            return Err(source_error(WarehouseError::ZeroQuantity));
+        }
+        if order.quantity > item.quantity {
+            return Err(source_error(WarehouseError::NotEnoughStock {
+                id: item.id,
+                requested: order.quantity as u64,
+                available: item.quantity as u64,
+            }));
+        }
+        let mut part = item.clone();
+        part.quantity = order.quantity;
+
+        let placements = self
+            .site_mut(&order.to)?
+            .store(part, filters)
+            .map_err(|error| NetworkError::Destination {
+                site: order.to.clone(),
+                error,
+            })?;
+
+        let source = self.site_mut(&order.from)?;
+        let taken = if whole {
+            source
+                .remove_zone(head.row, head.shelf, head.zone)
+                .map(|_| ())
+        } else {
+            source.take_quantity(head.row, head.shelf, head.zone, order.quantity)
+        };
+        if let Err(error) = taken {
+            self.site_mut(&order.to)?.unstore(&placements);
+            return Err(source_error(error));
+        }
+
+        Ok(placements)
+    }
+}
+
+// The zone holding the item that occupies `loc`, and the item.
+fn stored_at(warehouse: &Warehouse, loc: Location) -> Result<(Location, &Item), WarehouseError> {
+    if !warehouse.contains(loc.row, loc.shelf, loc.zone) {
+        return Err(WarehouseError::OutOfBounds(loc));
+    }
+    let head = Location {
+        zone: warehouse.zone(loc).reserved_by.unwrap_or(loc.zone),
+        ..loc
+    };
+    match &warehouse.zone(head).item {
+        Some(item) => Ok((head, item)),
+        None => Err(WarehouseError::ZoneEmpty(loc)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::default_filters;
+    use crate::item::Quality;
+    use crate::strategy::EmptiestShelf;
+    use crate::test_support::{self, warehouse};
+
+    // Every delivery in these tests belongs to lot L1.
+    fn item(id: u32, quantity: u32, quality: Quality) -> Item {
+        test_support::item(id, quantity, quality).with_lot("L1")
+    }
+
+    fn network() -> Network {
+        let mut north = warehouse("capacity 10\ngrid 2 1 2");
+        north
+            .add_zone(item(42, 8, Quality::Normal), 0, 0, 0)
+            .unwrap();
+        north
+            .add_zone(item(7, 1, Quality::Oversized { zones_needed: 2 }), 1, 0, 0)
+            .unwrap();
+        let mut south = warehouse("capacity 5\nrow 4");
+        south.set_strategy(Box::new(EmptiestShelf));
+        south
+            .add_zone(item(42, 3, Quality::Normal), 0, 0, 0)
+            .unwrap();
+
+        let mut network = Network::new();
+        network.add_site("north", north).unwrap();
+        network.add_site("south", south).unwrap();
+        network
+    }
+
+    fn order(from: &str, loc: Location, quantity: u32, to: &str) -> Transfer {
+        Transfer {
+            from: from.to_string(),
+            location: loc,
+            quantity,
+            to: to.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_stock_is_found_across_sites() {
+        let mut network = network();
+        assert_eq!(
+            network.add_site("north", warehouse("row 1")),
+            Err(NetworkError::DuplicateSite("north".to_string()))
+        );
+
+        assert_eq!(
+            network.find_by_id(42),
+            vec![
+                ("north", Location::new(0, 0, 0)),
+                ("south", Location::new(0, 0, 0))
+            ]
+        );
+        assert_eq!(network.total(42), 11);
+        let lots = network.lots(7);
+        assert_eq!(lots.len(), 1);
+        assert_eq!(lots[0].0, "north");
+        assert_eq!(
+            network.find_by_name("ITEM 7"),
+            vec![("north", Location::new(1, 0, 0))]
+        );
+        assert!(network.find_by_id(1).is_empty());
+        assert!(network.site("east").is_err());
+    }
+
+    #[test]
+    fn test_transfer_moves_units_to_the_other_site() {
+        let mut network = network();
+        let filters = default_filters();
+
+        let placements = network
+            .transfer(
+                &order("north", Location::new(0, 0, 0), 6, "south"),
+                &filters,
+            )
+            .unwrap();
+        // South tops up its own stock of the lot first, then uses its strategy.
+        assert_eq!(
+            placements,
+            vec![
+                Placement {
+                    location: Location::new(0, 0, 0),
+                    quantity: 2
+                },
+                Placement {
+                    location: Location::new(0, 0, 1),
+                    quantity: 4
+                },
+            ]
+        );
+        let north = network.site("north").unwrap();
+        assert_eq!(
+            north
+                .zone(Location::new(0, 0, 0))
+                .item
+                .as_ref()
+                .unwrap()
+                .quantity,
+            2
+        );
+        assert_eq!(network.total(42), 11);
+
+        // An oversized item goes whole, whichever of its zones is named.
+        let whole = order("north", Location::new(1, 0, 1), 1, "south");
+        assert_eq!(network.transfer(&whole, &filters).unwrap().len(), 1);
+        assert!(network.site("north").unwrap().find_by_id(7).is_empty());
+        assert_eq!(network.lots(7)[0].0, "south");
+    }
+
+    #[test]
+    fn test_failed_transfer_leaves_both_sites_alone() {
+        let mut network = network();
+        let filters = default_filters();
+        let from = Location::new(0, 0, 0);
+        let south = network.site_mut("south").unwrap();
+        for zone in 1..4 {
+            south
+                .add_zone(item(5, 1, Quality::Normal), 0, 0, zone)
+                .unwrap();
+        }
+        let exports = |network: &Network| {
+            network
+                .sites()
+                .map(|(_, warehouse)| crate::csv::export(warehouse))
+                .collect::<Vec<String>>()
+        };
+        let before = exports(&network);
+
+        // South only has room for 2 more units.
+        let error = network
+            .transfer(&order("north", from, 8, "south"), &filters)
+            .unwrap_err();
+        assert_eq!(
+            error,
+            NetworkError::Destination {
+                site: "south".to_string(),
+                error: WarehouseError::NoCapacity
+            }
+        );
+        assert!(error.to_string().contains("Nothing was transferred"));
+
+        assert!(matches!(
+            network.transfer(&order("north", from, 9, "south"), &filters),
+            Err(NetworkError::Source {
+                error: WarehouseError::NotEnoughStock { .. },
+                ..
+            })
+        ));
+        assert_eq!(
+            network.transfer(&order("north", from, 1, "north"), &filters),
+            Err(NetworkError::SameSite("north".to_string()))
+        );
+        assert_eq!(
+            network.transfer(&order("north", from, 1, "east"), &filters),
+            Err(NetworkError::UnknownSite("east".to_string()))
+        );
+        assert!(matches!(
+            network.transfer(
+                &order("north", Location::new(1, 0, 0), 0, "south"),
+                &filters
+            ),
+            Err(NetworkError::Unsplittable { .. })
+        ));
+
+        assert_eq!(exports(&network), before);
+
+        network
+            .site_mut("north")
+            .unwrap()
+            .quarantine(0, 0, 0)
+            .unwrap();
+        assert!(matches!(
+            network.transfer(&order("north", from, 1, "south"), &filters),
+            Err(NetworkError::Quarantined { .. })
+        ));
+    }
+}
@@ -0,0 +1,492 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       orders.rs
+ * Purpose:    Purchase-order receipts, stored all at once or not at all,
+ *             and outbound orders picked from stock, each with a status
+ *             kept in the warehouse file.
+ * =======================================================================
+ */
+
+use crate::filter::Filter;
+use crate::item::Item;
+use crate::journal::Event;
+use crate::route::{line_totals, PickPath};
+use crate::warehouse::{PickOrder, Placement, Warehouse, WarehouseError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Pending: nothing done yet. A receipt goes straight to complete; an
+// outbound order is partially fulfilled while some units are still owed.
+// Complete and cancelled orders cannot change any more.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OrderStatus {
+    Pending,
+    PartiallyFulfilled,
+    Complete,
+    Cancelled,
+}
+
+impl OrderStatus {
+    pub fn is_open(&self) -> bool {
+        matches!(self, OrderStatus::Pending | OrderStatus::PartiallyFulfilled)
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            OrderStatus::Pending => "pending",
+            OrderStatus::PartiallyFulfilled => "partially fulfilled",
+            OrderStatus::Complete => "complete",
+            OrderStatus::Cancelled => "cancelled",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+// Goods announced by a purchase order, to be stored together.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Receipt {
+    pub id: u32,
+    pub items: Vec<Item>,
+    pub status: OrderStatus,
+    // Where each item went, once the receipt is complete.
+    #[serde(default)]
+    pub placements: Vec<Vec<Placement>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OrderLine {
+    pub id: u32,
+    pub quantity: u32,
+    pub picked: u32,
+}
+
+impl OrderLine {
+    pub fn remaining(&self) -> u32 {
+        self.quantity - self.picked
+    }
+}
+
+// A pick list to send out.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutboundOrder {
+    pub id: u32,
+    pub lines: Vec<OrderLine>,
+    pub status: OrderStatus,
+}
+
+// Every receipt and outbound order, numbered from 1 in the order they were
+// made. Closed ones are kept as a record.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OrderBook {
+    pub receipts: Vec<Receipt>,
+    pub outbound: Vec<OutboundOrder>,
+}
+
+impl Warehouse {
+    pub fn orders(&self) -> &OrderBook {
+        &self.orders
+    }
+
+    pub fn receipt(&self, id: u32) -> Result<&Receipt, WarehouseError> {
+        self.orders
+            .receipts
+            .iter()
+            .find(|receipt| receipt.id == id)
+            .ok_or(WarehouseError::UnknownReceipt(id))
+    }
+
+    pub fn outbound_order(&self, id: u32) -> Result<&OutboundOrder, WarehouseError> {
+        self.orders
+            .outbound
+            .iter()
+            .find(|order| order.id == id)
+            .ok_or(WarehouseError::UnknownOrder(id))
+    }
+
+    // Records a pending receipt for these items and returns its number.
+    pub fn create_receipt(&mut self, items: Vec<Item>) -> Result<u32, WarehouseError> {
+        if items.is_empty() {
+            return Err(WarehouseError::EmptyOrder);
+        }
+        let receipt = Receipt {
+            id: self.orders.receipts.len() as u32 + 1,
+            items,
+            status: OrderStatus::Pending,
+            placements: vec![],
+        };
+        let id = receipt.id;
+        self.put_receipt(receipt);
+        Ok(id)
+    }
+
+    // Stores every item of a pending receipt like `store` does, received
+    // now. If one of them cannot be stored, the ones before it are taken
+    // back out and the receipt stays pending, so it can be tried again.
+    pub fn receive(
+        &mut self,
+        id: u32,
+        filters: &[Box<dyn Filter>],
+    ) -> Result<Vec<Vec<Placement>>, WarehouseError> {
+        let mut receipt = self.receipt(id)?.clone();
+        if receipt.status != OrderStatus::Pending {
+            return Err(WarehouseError::ReceiptClosed {
+                id,
+                status: receipt.status,
+            });
+        }
+
+        let now = self.now();
+        let mut placements = vec![];
+        for (index, item) in receipt.items.iter_mut().enumerate() {
+            item.timestamp = now;
+            match self.store(item.clone(), filters) {
+                Ok(placed) => placements.push(placed),
+                Err(reason) => {
+                    for placed in placements.iter().rev() {
+                        self.unstore(placed);
+                    }
+                    return Err(WarehouseError::ReceiptRejected {
+                        receipt: id,
+                        line: index + 1,
+                        reason: Box::new(reason),
+                    });
+                }
+            }
+        }
+
+        receipt.status = OrderStatus::Complete;
+        receipt.placements = placements.clone();
+        self.put_receipt(receipt);
+        Ok(placements)
+    }
+
+    pub fn cancel_receipt(&mut self, id: u32) -> Result<(), WarehouseError> {
+        let mut receipt = self.receipt(id)?.clone();
+        if receipt.status != OrderStatus::Pending {
+            return Err(WarehouseError::ReceiptClosed {
+                id,
+                status: receipt.status,
+            });
+        }
+        receipt.status = OrderStatus::Cancelled;
+        self.put_receipt(receipt);
+        Ok(())
+    }
+
+    // Records a pending outbound order for (item id, units) pairs and
+    // returns its number.
+    pub fn create_order(&mut self, lines: &[(u32, u32)]) -> Result<u32, WarehouseError> {
+        if lines.is_empty() {
+            return Err(WarehouseError::EmptyOrder);
+        }
+        if lines.iter().any(|(_, quantity)| *quantity == 0) {
+            return Err(WarehouseError::ZeroQuantity);
+        }
+        let order = OutboundOrder {
+            id: self.orders.outbound.len() as u32 + 1,
+            lines: lines
+                .iter()
+                .map(|&(id, quantity)| OrderLine {
+                    id,
+                    quantity,
+                    picked: 0,
+                })
+                .collect(),
+            status: OrderStatus::Pending,
+        };
+        let id = order.id;
+        self.put_order(order);
+        Ok(id)
+    }
+
+    // Picks what stock allows of every line still owed and returns the
+    // picks in walking order. Lines short of stock are picked in part and
+    // the order waits, partially fulfilled, for the rest. Every line is
+    // planned before any stock is taken.
+    pub fn fulfil(&mut self, id: u32, pick_order: PickOrder) -> Result<PickPath, WarehouseError> {
+        let mut order = self.outbound_order(id)?.clone();
+        if !order.status.is_open() {
+            return Err(WarehouseError::OrderClosed {
+                id,
+                status: order.status,
+            });
+        }
+
+        let mut owed = self.owed_now(&order);
+        let path = self.plan_path(&owed, pick_order)?;
+        for (_, pick) in &path.picks {
+            let loc = pick.location;
+            self.take_quantity(loc.row, loc.shelf, loc.zone, pick.quantity)?;
+        }
+
+        // The picked units go to the lines of their item in order.
+        for line in &mut order.lines {
+            if let Some((_, units)) = owed.iter_mut().find(|(id, _)| *id == line.id) {
+                let given = (line.remaining() as u64).min(*units);
+                *units -= given;
+                line.picked += given as u32;
+            }
+        }
+
+        order.status = if order.lines.iter().all(|line| line.remaining() == 0) {
+            OrderStatus::Complete
+        } else if order.lines.iter().any(|line| line.picked > 0) {
+            OrderStatus::PartiallyFulfilled
+        } else {
+            OrderStatus::Pending
+        };
+        self.put_order(order);
+        Ok(path)
+    }
+
+    // What is still owed on an open order and can be picked now, in
+    // walking order. Nothing is taken.
+    pub fn order_path(&self, id: u32, pick_order: PickOrder) -> Result<PickPath, WarehouseError> {
+        let order = self.outbound_order(id)?;
+        if !order.status.is_open() {
+            return Err(WarehouseError::OrderClosed {
+                id,
+                status: order.status,
+            });
+        }
+        self.plan_path(&self.owed_now(order), pick_order)
+    }
+
+    // The units still owed on the order that stock allows picking now, per
+    // item. Lines for the same item share its stock.
+    fn owed_now(&self, order: &OutboundOrder) -> Vec<(u32, u64)> {
+        let owed: Vec<(u32, u32)> = order
+            .lines
+            .iter()
+            .map(|line| (line.id, line.remaining()))
+            .collect();
+        line_totals(&owed)
+            .into_iter()
+            .map(|(id, units)| (id, units.min(self.available(id))))
+            .filter(|(_, units)| *units > 0)
+            .collect()
+    }
+
+    // Stops an open order. Units already picked stay picked.
+    pub fn cancel_order(&mut self, id: u32) -> Result<(), WarehouseError> {
+        let mut order = self.outbound_order(id)?.clone();
+        if !order.status.is_open() {
+            return Err(WarehouseError::OrderClosed {
+                id,
+                status: order.status,
+            });
+        }
+        order.status = OrderStatus::Cancelled;
+        self.put_order(order);
+        Ok(())
+    }
+
+    // Adds the receipt, or replaces the one with its number.
+    pub(crate) fn put_receipt(&mut self, receipt: Receipt) {
+        self.record(Event::ReceiptSaved {
+            receipt: receipt.clone(),
+        });
+        let receipts = &mut self.orders.receipts;
+        match receipts.iter_mut().find(|old| old.id == receipt.id) {
+            Some(old) => *old = receipt,
+            None => receipts.push(receipt),
+        }
+    }
+
+    pub(crate) fn put_order(&mut self, order: OutboundOrder) {
+        self.record(Event::OrderSaved {
+            order: order.clone(),
+        });
+        let outbound = &mut self.orders.outbound;
+        match outbound.iter_mut().find(|old| old.id == order.id) {
+            Some(old) => *old = order,
+            None => outbound.push(order),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::filter::default_filters;
+    use crate::item::Quality;
+    use crate::test_support::{item, warehouse};
+    use crate::warehouse::Location;
+
+    #[test]
+    fn test_receipt_is_stored_whole_or_not_at_all() {
+        let mut warehouse = warehouse("capacity 10\nrow 4");
+        let filters = default_filters();
+        warehouse
+            .add_zone(item(1, 4, Quality::Normal), 0, 0, 0)
+            .unwrap();
+        warehouse
+            .add_zone(item(5, 1, Quality::Normal), 0, 0, 3)
+            .unwrap();
+        let before = crate::csv::export(&warehouse);
+
+        // 6 units top up zone 0 and 10 fill zone 1, then the crate has no room.
+        let id = warehouse
+            .create_receipt(vec![
+                item(1, 6, Quality::Normal),
+                item(2, 10, Quality::Normal),
+                item(3, 1, Quality::Oversized { zones_needed: 2 }),
+            ])
+            .unwrap();
+        assert_eq!(
+            warehouse.receive(id, &filters),
+            Err(WarehouseError::ReceiptRejected {
+                receipt: id,
+                line: 3,
+                reason: Box::new(WarehouseError::NoCapacity)
+            })
+        );
+        assert_eq!(crate::csv::export(&warehouse), before);
+        assert_eq!(warehouse.receipt(id).unwrap().status, OrderStatus::Pending);
+
+        warehouse.remove_zone(0, 0, 3).unwrap();
+        let placements = warehouse.receive(id, &filters).unwrap();
+        assert_eq!(placements.len(), 3);
+        assert_eq!(placements[2][0].location, Location::new(0, 0, 2));
+        let receipt = warehouse.receipt(id).unwrap();
+        assert_eq!(receipt.status, OrderStatus::Complete);
+        assert_eq!(receipt.placements, placements);
+        assert_eq!(
+            warehouse
+                .zone(Location::new(0, 0, 1))
+                .item
+                .as_ref()
+                .unwrap()
+                .timestamp,
+            warehouse.now()
+        );
+
+        assert_eq!(
+            warehouse.receive(id, &filters),
+            Err(WarehouseError::ReceiptClosed {
+                id,
+                status: OrderStatus::Complete
+            })
+        );
+        assert!(warehouse.cancel_receipt(id).is_err());
+        assert_eq!(
+            warehouse.create_receipt(vec![]),
+            Err(WarehouseError::EmptyOrder)
+        );
+        assert_eq!(
+            warehouse.receive(9, &filters),
+            Err(WarehouseError::UnknownReceipt(9))
+        );
+    }
+
+    #[test]
+    fn test_outbound_order_lifecycle() {
+        let mut warehouse = warehouse("capacity 10\nrow 3");
+        warehouse
+            .add_zone(item(1, 5, Quality::Normal), 0, 0, 0)
+            .unwrap();
+        warehouse
+            .add_zone(item(2, 3, Quality::Normal), 0, 0, 1)
+            .unwrap();
+
+        let id = warehouse.create_order(&[(1, 4), (2, 5)]).unwrap();
+        assert_eq!(
+            warehouse.outbound_order(id).unwrap().status,
+            OrderStatus::Pending
+        );
+
+        assert_eq!(
+            warehouse
+                .order_path(id, PickOrder::Fifo)
+                .unwrap()
+                .picks
+                .len(),
+            2
+        );
+        let path = warehouse.fulfil(id, PickOrder::Fifo).unwrap();
+        assert_eq!(path.distance, 1);
+        assert_eq!(
+            path.picks,
+            vec![
+                (
+                    1,
+                    Placement {
+                        location: Location::new(0, 0, 0),
+                        quantity: 4
+                    }
+                ),
+                (
+                    2,
+                    Placement {
+                        location: Location::new(0, 0, 1),
+                        quantity: 3
+                    }
+                ),
+            ]
+        );
+        let order = warehouse.outbound_order(id).unwrap();
+        assert_eq!(order.status, OrderStatus::PartiallyFulfilled);
+        assert_eq!(order.lines[1].remaining(), 2);
+
+        // Nothing to pick: the order waits as it is.
+        assert!(warehouse
+            .fulfil(id, PickOrder::Fifo)
+            .unwrap()
+            .picks
+            .is_empty());
+        warehouse
+            .add_zone(item(2, 6, Quality::Normal), 0, 0, 2)
+            .unwrap();
+        assert_eq!(
+            warehouse.fulfil(id, PickOrder::Fifo).unwrap().picks.len(),
+            1
+        );
+        assert_eq!(
+            warehouse.outbound_order(id).unwrap().status,
+            OrderStatus::Complete
+        );
+        assert_eq!(warehouse.available(2), 4);
+        assert!(warehouse.cancel_order(id).is_err());
+
+        let other = warehouse.create_order(&[(3, 1)]).unwrap();
+        warehouse.cancel_order(other).unwrap();
+        assert_eq!(
+            warehouse.fulfil(other, PickOrder::Fifo),
+            Err(WarehouseError::OrderClosed {
+                id: other,
+                status: OrderStatus::Cancelled
+            })
+        );
+        assert_eq!(
+            warehouse.create_order(&[(1, 0)]),
+            Err(WarehouseError::ZeroQuantity)
+        );
+    }
+
+    #[test]
+    fn test_lines_for_one_item_share_its_stock() {
+        let mut warehouse = warehouse("capacity 10\nrow 3");
+        warehouse
+            .add_zone(item(7, 5, Quality::Normal), 0, 0, 0)
+            .unwrap();
+        let id = warehouse.create_order(&[(7, 5), (7, 5)]).unwrap();
+        let units = |path: &PickPath| -> u32 {
+            path.picks
+                .iter()
+                .map(|(_, placement)| placement.quantity)
+                .sum()
+        };
+
+        let planned = warehouse.order_path(id, PickOrder::Fifo).unwrap();
+        assert_eq!(units(&planned), 5);
+        let picked = warehouse.fulfil(id, PickOrder::Fifo).unwrap();
+        assert_eq!(units(&picked), 5);
+        let order = warehouse.outbound_order(id).unwrap();
+        assert_eq!(order.status, OrderStatus::PartiallyFulfilled);
+        assert_eq!(order.lines[0].remaining(), 0);
+        assert_eq!(order.lines[1].remaining(), 5);
+    }
+}
@@ -0,0 +1,492 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       report.rs
+ * Purpose:    Occupancy figures for the warehouse: how many zones are in
+ *             use per row and shelf, what oversized goods take up, stock
+ *             per quality, and a heat map of the grid for the terminal.
+ *             Also the text of the searches and reports that the menu
+ *             and the command line print.
+ * =======================================================================
+ */
+
+use crate::csv::LineReport;
+use crate::network::Network;
+use crate::orders::OrderStatus;
+use crate::route::PickPath;
+use crate::warehouse::{Warehouse, Zone};
+use std::collections::BTreeMap;
+use std::ops::AddAssign;
+
+// Zone counts for a shelf, a row or the whole warehouse.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Usage {
+    pub zones: usize,
+    // Zones holding an item or reserved by one; includes `oversized`.
+    pub used: usize,
+    // Zones taken up by oversized goods.
+    pub oversized: usize,
+}
+
+impl Usage {
+    fn of(zones: &[Zone]) -> Usage {
+        let mut usage = Usage {
+            zones: zones.len(),
+            ..Usage::default()
+        };
+        for zone in zones {
+            if !zone.is_free() {
+                usage.used += 1;
+            }
+            if is_oversized(zone) {
+                usage.oversized += 1;
+            }
+        }
+        usage
+    }
+
+    pub fn free(&self) -> usize {
+        self.zones - self.used
+    }
+
+    // Share of the zones in use, 0 to 100.
+    pub fn percent(&self) -> usize {
+        (self.used * 100).checked_div(self.zones).unwrap_or(0)
+    }
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Usage) {
+        self.zones += other.zones;
+        self.used += other.used;
+        self.oversized += other.oversized;
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RowUsage {
+    pub total: Usage,
+    pub shelves: Vec<Usage>,
+}
+
+// How much of one quality is stored: zones holding it and units.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Stock {
+    pub items: usize,
+    pub units: u64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Occupancy {
+    pub rows: Vec<RowUsage>,
+    // Keyed by Quality::name; every quality is listed, even when absent.
+    pub qualities: BTreeMap<&'static str, Stock>,
+}
+
+impl Occupancy {
+    pub fn total(&self) -> Usage {
+        let mut total = Usage::default();
+        for row in &self.rows {
+            total += row.total;
+        }
+        total
+    }
+}
+
+fn is_oversized(zone: &Zone) -> bool {
+    zone.reserved_by.is_some()
+        || zone
+            .item
+            .as_ref()
+            .is_some_and(|item| item.zones_needed() > 1)
+}
+
+// One character per zone: how full it is, or 'O' for oversized goods.
+fn heat(zone: &Zone) -> char {
+    const LEVELS: [char; 4] = ['-', '=', '+', '*'];
+
+    if is_oversized(zone) {
+        return 'O';
+    }
+    match &zone.item {
+        None => '.',
+        Some(item) if item.quantity >= zone.capacity => '#',
+        Some(item) => LEVELS[(item.quantity as usize * 4 / zone.capacity as usize).min(3)],
+    }
+}
+
+pub const HEAT_MAP_LEGEND: &str = ". free  - = + * under 25/50/75/100% full  # full  O oversized";
+
+impl Warehouse {
+    pub fn occupancy(&self) -> Occupancy {
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| {
+                let shelves: Vec<Usage> = row
+                    .shelves
+                    .iter()
+                    .map(|shelf| Usage::of(&shelf.zones))
+                    .collect();
+                let mut total = Usage::default();
+                for usage in &shelves {
+                    total += *usage;
+                }
+                RowUsage { total, shelves }
+            })
+            .collect();
+
+        let mut qualities = BTreeMap::new();
+        for name in ["normal", "fragile", "oversized"] {
+            qualities.insert(name, Stock::default());
+        }
+        for (_, item) in self.items() {
+            let stock = qualities.entry(item.quality.name()).or_default();
+            stock.items += 1;
+            stock.units += item.quantity as u64;
+        }
+
+        Occupancy { rows, qualities }
+    }
+
+    // The grid as text, one line per row and a bracketed group per shelf.
+    pub fn heat_map(&self) -> String {
+        let mut lines = vec![];
+        for (r_idx, row) in self.rows.iter().enumerate() {
+            let shelves: Vec<String> = row
+                .shelves
+                .iter()
+                .map(|shelf| format!("[{}]", shelf.zones.iter().map(heat).collect::<String>()))
+                .collect();
+            lines.push(format!("Row {:<3} {}", r_idx, shelves.join(" ")));
+        }
+        lines.join("\n")
+    }
+}
+
+// Where the item with this id is stored, its total and its lots.
+pub fn search_by_id(warehouse: &Warehouse, id: u32) -> Vec<String> {
+    let locations = warehouse.find_by_id(id);
+    let mut lines = vec![];
+    let mut units = 0;
+
+    for loc in &locations {
+        let quantity = warehouse
+            .zone(*loc)
+            .item
+            .as_ref()
+            .map_or(0, |item| item.quantity);
+        lines.push(format!("Found at {} ({} units)", loc, quantity));
+        units += quantity as u64;
+    }
+    lines.push(format!(
+        "Total for ID {}: {} units in {} zones",
+        id,
+        units,
+        locations.len()
+    ));
+    for lot in warehouse.lots(id) {
+        lines.push(lot.to_string());
+    }
+
+    lines
+}
+
+pub fn search_by_name(warehouse: &Warehouse, name: &str) -> Vec<String> {
+    let locations = warehouse.find_by_name(name);
+    let mut lines: Vec<String> = locations
+        .iter()
+        .map(|loc| format!("Found at {}", loc))
+        .collect();
+
+    lines.push(format!("Total items named '{}': {}", name, locations.len()));
+    lines
+}
+
+// Every item, sorted by name.
+pub fn all_items(warehouse: &Warehouse) -> Vec<String> {
+    warehouse
+        .items_by_name()
+        .iter()
+        .map(|item| item.details())
+        .collect()
+}
+
+// The stock of the item with this id at every site, with its lots.
+pub fn network_search_by_id(network: &Network, id: u32) -> Vec<String> {
+    let mut lines = vec![];
+
+    for (site, lots) in network.lots(id) {
+        let units: u64 = lots.iter().map(|lot| lot.units).sum();
+        lines.push(format!("Site {}: {} units", site, units));
+        for lot in &lots {
+            lines.push(format!("  {}", lot));
+            for loc in &lot.locations {
+                lines.push(format!("    at {}", loc));
+            }
+        }
+    }
+    lines.push(format!(
+        "Total for ID {} across all sites: {} units",
+        id,
+        network.total(id)
+    ));
+
+    lines
+}
+
+pub fn network_search_by_name(network: &Network, name: &str) -> Vec<String> {
+    let found = network.find_by_name(name);
+    let mut lines: Vec<String> = found
+        .iter()
+        .map(|(site, loc)| format!("Found at {} in site {}", loc, site))
+        .collect();
+
+    lines.push(format!(
+        "Total items named '{}' across all sites: {}",
+        name,
+        found.len()
+    ));
+    lines
+}
+
+// Items expiring within `days`, grouped by the days they have left.
+pub fn near_expiry(warehouse: &Warehouse, days: i64) -> Vec<String> {
+    let mut lines = vec![];
+    let mut total = 0;
+
+    for (days_left, items) in warehouse.expiry_report(days) {
+        lines.push(match days_left {
+            ..=-1 => format!("Expired {} days ago:", -days_left),
+            0 => "Expires today:".to_string(),
+            _ => format!("Expires in {} days:", days_left),
+        });
+        for (loc, item) in &items {
+            let note = if item.quarantined {
+                " (quarantined)"
+            } else {
+                ""
+            };
+            lines.push(format!(
+                "  '{}' (ID: {}) at {}{}",
+                item.name, item.id, loc, note
+            ));
+        }
+        total += items.len();
+    }
+    lines.push(format!("Total items that expire: {}", total));
+
+    lines
+}
+
+pub fn occupancy_report(warehouse: &Warehouse) -> Vec<String> {
+    let occupancy = warehouse.occupancy();
+    let mut lines = vec![];
+
+    for (r_idx, row) in occupancy.rows.iter().enumerate() {
+        lines.push(format!(
+            "Row {}: {}/{} zones used ({}%)",
+            r_idx,
+            row.total.used,
+            row.total.zones,
+            row.total.percent()
+        ));
+        for (s_idx, shelf) in row.shelves.iter().enumerate() {
+            lines.push(format!(
+                "  Shelf {}: {} used, {} free, {} oversized",
+                s_idx,
+                shelf.used,
+                shelf.free(),
+                shelf.oversized
+            ));
+        }
+    }
+
+    let total = occupancy.total();
+    lines.push(format!(
+        "Total: {} used, {} free of {} zones ({}%), {} taken by oversized goods",
+        total.used,
+        total.free(),
+        total.zones,
+        total.percent(),
+        total.oversized
+    ));
+    for (quality, stock) in &occupancy.qualities {
+        lines.push(format!(
+            "{}: {} items, {} units",
+            quality, stock.items, stock.units
+        ));
+    }
+    lines.push(String::new());
+    lines.push(warehouse.heat_map());
+    lines.push(HEAT_MAP_LEGEND.to_string());
+
+    lines
+}
+
+// Every receipt and outbound order with its status.
+pub fn orders(warehouse: &Warehouse) -> Vec<String> {
+    let book = warehouse.orders();
+    let mut lines = vec![];
+
+    for receipt in &book.receipts {
+        let units: u64 = receipt.items.iter().map(|item| item.quantity as u64).sum();
+        lines.push(format!(
+            "Receipt {}: {}, {} items, {} units",
+            receipt.id,
+            receipt.status,
+            receipt.items.len(),
+            units
+        ));
+    }
+    for order in &book.outbound {
+        lines.push(format!("Order {}: {}", order.id, order.status));
+        for line in &order.lines {
+            lines.push(format!(
+                "  ID {}: {} of {} picked",
+                line.id, line.picked, line.quantity
+            ));
+        }
+    }
+    let open = book
+        .outbound
+        .iter()
+        .filter(|order| order.status.is_open())
+        .count();
+    let pending = book
+        .receipts
+        .iter()
+        .filter(|receipt| receipt.status == OrderStatus::Pending)
+        .count();
+    lines.push(format!(
+        "Pending receipts: {}, open orders: {}",
+        pending, open
+    ));
+
+    lines
+}
+
+// A pick list in walking order, numbered, with the steps it takes.
+pub fn pick_path(path: &PickPath) -> Vec<String> {
+    let mut lines: Vec<String> = path
+        .picks
+        .iter()
+        .enumerate()
+        .map(|(index, (id, placement))| format!("{}. ID {} from {}", index + 1, id, placement))
+        .collect();
+    lines.push(format!("Walking distance: {} steps", path.distance));
+    lines
+}
+
+// What happened to every line of a CSV import.
+pub fn import_report(reports: &[LineReport]) -> Vec<String> {
+    let mut lines = vec![];
+    let mut rejected = 0;
+
+    for report in reports {
+        match &report.result {
+            Ok(placements) => {
+                for placement in placements {
+                    lines.push(format!("Line {}: stored at {}", report.line, placement));
+                }
+            }
+            Err(reason) => {
+                lines.push(format!("Line {}: rejected: {}", report.line, reason));
+                rejected += 1;
+            }
+        }
+    }
+    lines.push(format!(
+        "Accepted: {}, rejected: {}",
+        reports.len() - rejected,
+        rejected
+    ));
+
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::parse_date;
+    use crate::item::Quality;
+    use crate::test_support::{item, warehouse};
+
+    #[test]
+    fn test_occupancy_and_heat_map() {
+        let mut warehouse = warehouse("capacity 8\nrow 4 2\nrow 3");
+        warehouse
+            .add_zone(item(1, 8, Quality::Normal), 0, 0, 0)
+            .unwrap();
+        warehouse
+            .add_zone(item(2, 1, Quality::Oversized { zones_needed: 2 }), 0, 0, 2)
+            .unwrap();
+        warehouse
+            .add_zone(item(3, 3, Quality::Normal), 0, 1, 1)
+            .unwrap();
+        warehouse
+            .add_zone(item(4, 1, Quality::Normal), 1, 0, 0)
+            .unwrap();
+
+        let occupancy = warehouse.occupancy();
+        let first = &occupancy.rows[0];
+        assert_eq!(
+            first.shelves[0],
+            Usage {
+                zones: 4,
+                used: 3,
+                oversized: 2
+            }
+        );
+        assert_eq!(first.total.free(), 2);
+        assert_eq!(occupancy.rows[1].total.percent(), 33);
+
+        let total = occupancy.total();
+        assert_eq!((total.zones, total.used, total.oversized), (9, 5, 2));
+        assert_eq!(
+            occupancy.qualities["normal"],
+            Stock {
+                items: 3,
+                units: 12
+            }
+        );
+        assert_eq!(occupancy.qualities["fragile"], Stock::default());
+        assert_eq!(occupancy.qualities["oversized"].items, 1);
+
+        assert_eq!(warehouse.heat_map(), "Row 0   [#.OO] [.=]\nRow 1   [-..]");
+    }
+
+    #[test]
+    fn test_search_and_expiry_text() {
+        let mut warehouse = warehouse("row 3");
+        let milk = Quality::Fragile {
+            expiry_date: parse_date("2030-01-11").unwrap(),
+            max_shelf: 0,
+        };
+        warehouse.add_zone(item(1, 4, milk), 0, 0, 0).unwrap();
+        warehouse
+            .add_zone(item(2, 2, Quality::Normal), 0, 0, 2)
+            .unwrap();
+
+        assert_eq!(
+            search_by_id(&warehouse, 1),
+            vec![
+                "Found at Row 0, Shelf 0, Zone 0 (4 units)",
+                "Total for ID 1: 4 units in 1 zones",
+                "Lot -, expires 2030-01-11, received 1970-01-01: 4 units in 1 zones",
+            ]
+        );
+        assert_eq!(
+            search_by_name(&warehouse, "nothing"),
+            vec!["Total items named 'nothing': 0"]
+        );
+        assert_eq!(
+            near_expiry(&warehouse, 3),
+            vec![
+                "Expires in 1 days:",
+                "  'Item 1' (ID: 1) at Row 0, Shelf 0, Zone 0",
+                "Total items that expire: 1",
+            ]
+        );
+    }
+}
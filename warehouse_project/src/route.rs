@@ -0,0 +1,446 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       route.rs
+ * Purpose:    Walking distances over the grid and the order in which to
+ *             visit zones so a picker walks as little as possible.
+ * =======================================================================
+ */
+
+use crate::warehouse::{Location, PickOrder, Placement, Warehouse, WarehouseError};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/*
+ * A picker starts at the front end of row 0. Along a row the zones of its
+ * shelves follow each other: `zone` steps between neighbouring zones and
+ * `shelf` extra steps where one shelf ends and the next begins. Going to
+ * another row costs `row` steps per row crossed; with `Aisles::Ends` rows
+ * can only be left at their front or back end, with `Aisles::Open` straight
+ * across.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DistanceModel {
+    pub row: u64,
+    pub shelf: u64,
+    pub zone: u64,
+    pub aisles: Aisles,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Aisles {
+    Ends,
+    Open,
+}
+
+impl Default for DistanceModel {
+    fn default() -> Self {
+        DistanceModel {
+            row: 5,
+            shelf: 1,
+            zone: 1,
+            aisles: Aisles::Ends,
+        }
+    }
+}
+
+// How the stops of a route are put in order.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum RouteStyle {
+    // Rows in turn, walking every other row back to front.
+    #[default]
+    Serpentine,
+    // Always the closest stop not visited yet.
+    Nearest,
+    // Whichever of the two is shorter for these stops.
+    Shortest,
+}
+
+impl RouteStyle {
+    pub fn parse(text: &str) -> Result<RouteStyle, String> {
+        match text.to_lowercase().as_str() {
+            "serpentine" => Ok(RouteStyle::Serpentine),
+            "nearest" => Ok(RouteStyle::Nearest),
+            "shortest" => Ok(RouteStyle::Shortest),
+            _ => Err(format!("unknown route style '{}'", text)),
+        }
+    }
+}
+
+impl fmt::Display for RouteStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            RouteStyle::Serpentine => "serpentine",
+            RouteStyle::Nearest => "nearest",
+            RouteStyle::Shortest => "shortest",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+// The zones to visit in walking order and the steps from the start to the
+// last of them.
+#[derive(Debug, PartialEq)]
+pub struct Route {
+    pub stops: Vec<Location>,
+    pub distance: u64,
+}
+
+// A pick list in walking order: (item id, where and how many).
+#[derive(Debug, PartialEq)]
+pub struct PickPath {
+    pub picks: Vec<(u32, Placement)>,
+    pub distance: u64,
+}
+
+// A point on the floor: a row and the steps from its front end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Spot {
+    row: usize,
+    offset: u64,
+}
+
+const START: Spot = Spot { row: 0, offset: 0 };
+
+impl Warehouse {
+    pub fn distance_model(&self) -> DistanceModel {
+        self.distance
+    }
+
+    pub fn set_distance_model(&mut self, model: DistanceModel) {
+        self.distance = model;
+    }
+
+    pub fn route_style(&self) -> RouteStyle {
+        self.route_style
+    }
+
+    pub fn set_route_style(&mut self, style: RouteStyle) {
+        self.route_style = style;
+    }
+
+    // Steps between two zones.
+    pub fn distance(&self, from: Location, to: Location) -> u64 {
+        self.walk(self.spot(from), self.spot(to))
+    }
+
+    // Orders the zones with the warehouse's route style. Zones named twice
+    // are visited once.
+    pub fn route(&self, locations: &[Location]) -> Route {
+        self.route_with(locations, self.route_style)
+    }
+
+    pub fn route_with(&self, locations: &[Location], style: RouteStyle) -> Route {
+        let mut stops = locations.to_vec();
+        stops.sort();
+        stops.dedup();
+
+        let stops = match style {
+            RouteStyle::Serpentine => self.serpentine(stops),
+            RouteStyle::Nearest => self.nearest(stops),
+            RouteStyle::Shortest => {
+                let serpentine = self.route_with(&stops, RouteStyle::Serpentine);
+                let nearest = self.route_with(&stops, RouteStyle::Nearest);
+                return if nearest.distance < serpentine.distance {
+                    nearest
+                } else {
+                    serpentine
+                };
+            }
+        };
+        let distance = self.length(&stops);
+        Route { stops, distance }
+    }
+
+    // What to pick for (item id, units) lines, as `pick` would choose it,
+    // in walking order. Lines for the same item are added up first, so they
+    // do not plan the same units twice. Nothing is taken.
+    pub fn pick_path(
+        &self,
+        lines: &[(u32, u32)],
+        order: PickOrder,
+    ) -> Result<PickPath, WarehouseError> {
+        self.plan_path(&line_totals(lines), order)
+    }
+
+    // Like `pick_path`, for lines already added up by `line_totals`.
+    pub(crate) fn plan_path(
+        &self,
+        totals: &[(u32, u64)],
+        order: PickOrder,
+    ) -> Result<PickPath, WarehouseError> {
+        let mut picks = vec![];
+        for &(id, quantity) in totals {
+            for placement in self.plan_pick(id, quantity, order)? {
+                picks.push((id, placement));
+            }
+        }
+        Ok(self.walking_order(picks))
+    }
+
+    // Puts picks in the order of a route through their zones.
+    pub fn walking_order(&self, mut picks: Vec<(u32, Placement)>) -> PickPath {
+        let locations: Vec<Location> = picks
+            .iter()
+            .map(|(_, placement)| placement.location)
+            .collect();
+        let route = self.route(&locations);
+        picks.sort_by_key(|(_, placement)| {
+            route
+                .stops
+                .iter()
+                .position(|stop| *stop == placement.location)
+        });
+        PickPath {
+            picks,
+            distance: route.distance,
+        }
+    }
+
+    fn spot(&self, loc: Location) -> Spot {
+        let row = &self.rows[loc.row];
+        let zones_before: usize = row.shelves[..loc.shelf]
+            .iter()
+            .map(|shelf| shelf.zones.len())
+            .sum();
+        Spot {
+            row: loc.row,
+            offset: (zones_before + loc.zone) as u64 * self.distance.zone
+                + loc.shelf as u64 * self.distance.shelf,
+        }
+    }
+
+    // Steps from the front to the back end of the longest row.
+    fn depth(&self) -> u64 {
+        self.rows
+            .iter()
+            .enumerate()
+            .filter_map(|(r_idx, row)| {
+                let s_idx = row.shelves.len().checked_sub(1)?;
+                let z_idx = row.shelves[s_idx].zones.len().checked_sub(1)?;
+                Some(self.spot(Location::new(r_idx, s_idx, z_idx)).offset)
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn walk(&self, from: Spot, to: Spot) -> u64 {
+        if from.row == to.row {
+            return from.offset.abs_diff(to.offset);
+        }
+        let across = from.row.abs_diff(to.row) as u64 * self.distance.row;
+        let along = match self.distance.aisles {
+            Aisles::Open => from.offset.abs_diff(to.offset),
+            Aisles::Ends => {
+                let depth = self.depth();
+                (from.offset + to.offset).min(2 * depth - from.offset - to.offset)
+            }
+        };
+        across + along
+    }
+
+    fn length(&self, stops: &[Location]) -> u64 {
+        let mut at = START;
+        let mut total = 0;
+        for stop in stops {
+            let next = self.spot(*stop);
+            total += self.walk(at, next);
+            at = next;
+        }
+        total
+    }
+
+    // `stops` is sorted in grid order.
+    fn serpentine(&self, stops: Vec<Location>) -> Vec<Location> {
+        let mut rows: Vec<Vec<Location>> = vec![];
+        for stop in stops {
+            match rows.last_mut() {
+                Some(row) if row[0].row == stop.row => row.push(stop),
+                _ => rows.push(vec![stop]),
+            }
+        }
+
+        let mut route = vec![];
+        for (index, mut row) in rows.into_iter().enumerate() {
+            if index % 2 == 1 {
+                row.reverse();
+            }
+            route.extend(row);
+        }
+        route
+    }
+
+    fn nearest(&self, mut stops: Vec<Location>) -> Vec<Location> {
+        let mut at = START;
+        let mut route = vec![];
+        while !stops.is_empty() {
+            let (index, _) = stops
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, stop)| self.walk(at, self.spot(**stop)))
+                .unwrap();
+            let stop = stops.remove(index);
+            at = self.spot(stop);
+            route.push(stop);
+        }
+        route
+    }
+}
+
+// The units of (item id, units) lines per item, in the order the items
+// first appear. Totals are kept in u64 so no number of lines can overflow.
+pub(crate) fn line_totals(lines: &[(u32, u32)]) -> Vec<(u32, u64)> {
+    let mut totals: Vec<(u32, u64)> = vec![];
+    for &(id, quantity) in lines {
+        match totals.iter_mut().find(|(other, _)| *other == id) {
+            Some((_, total)) => *total += quantity as u64,
+            None => totals.push((id, quantity as u64)),
+        }
+    }
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item::Quality;
+    use crate::test_support::{item, warehouse};
+
+    #[test]
+    fn test_distance_models() {
+        // Every row: two shelves of 3 zones, offsets 0 1 2 | 4 5 6.
+        let mut warehouse = warehouse("grid 3 2 3\nwalk 5 1 1");
+        let front = Location::new(0, 0, 0);
+        assert_eq!(warehouse.distance(front, Location::new(0, 1, 0)), 4);
+        // Two rows over, out by the front end or round the back.
+        assert_eq!(warehouse.distance(front, Location::new(2, 0, 1)), 11);
+        assert_eq!(
+            warehouse.distance(Location::new(0, 1, 1), Location::new(1, 0, 1)),
+            11
+        );
+
+        warehouse.set_distance_model(DistanceModel {
+            aisles: Aisles::Open,
+            ..warehouse.distance_model()
+        });
+        assert_eq!(
+            warehouse.distance(Location::new(0, 1, 1), Location::new(1, 0, 1)),
+            9
+        );
+    }
+
+    #[test]
+    fn test_serpentine_and_nearest_routes() {
+        let warehouse = warehouse("grid 3 2 3\nwalk 5 1 1");
+        let stops = [
+            Location::new(2, 0, 0),
+            Location::new(0, 1, 2),
+            Location::new(1, 0, 0),
+            Location::new(0, 0, 1),
+            Location::new(1, 1, 2),
+            Location::new(0, 0, 1),
+        ];
+
+        let serpentine = warehouse.route_with(&stops, RouteStyle::Serpentine);
+        assert_eq!(
+            serpentine.stops,
+            vec![
+                Location::new(0, 0, 1),
+                Location::new(0, 1, 2),
+                Location::new(1, 1, 2),
+                Location::new(1, 0, 0),
+                Location::new(2, 0, 0),
+            ]
+        );
+        // 6 to the back of row 0, 5 across, 6 down row 1, 5 across.
+        assert_eq!(serpentine.distance, 22);
+
+        let nearest = warehouse.route_with(&stops, RouteStyle::Nearest);
+        assert_eq!(nearest.stops[0], Location::new(0, 0, 1));
+        assert_eq!(nearest.stops.len(), 5);
+        let shortest = warehouse.route_with(&stops, RouteStyle::Shortest);
+        assert!(shortest.distance <= serpentine.distance.min(nearest.distance));
+        assert_eq!(warehouse.route(&[]).distance, 0);
+    }
+
+    #[test]
+    fn test_pick_path_is_in_walking_order() {
+        let mut warehouse = warehouse("capacity 5\ngrid 2 1 3\nwalk 5 1 1");
+        warehouse
+            .add_zone(item(1, 5, Quality::Normal), 1, 0, 0)
+            .unwrap();
+        warehouse
+            .add_zone(item(2, 5, Quality::Normal), 0, 0, 2)
+            .unwrap();
+        warehouse
+            .add_zone(item(1, 5, Quality::Normal), 0, 0, 0)
+            .unwrap();
+
+        let path = warehouse
+            .pick_path(&[(1, 7), (2, 1)], PickOrder::Fifo)
+            .unwrap();
+        let walk: Vec<(u32, Location)> = path
+            .picks
+            .iter()
+            .map(|(id, placement)| (*id, placement.location))
+            .collect();
+        assert_eq!(
+            walk,
+            vec![
+                (1, Location::new(0, 0, 0)),
+                (2, Location::new(0, 0, 2)),
+                (1, Location::new(1, 0, 0)),
+            ]
+        );
+        assert_eq!(path.distance, 9);
+        assert_eq!(warehouse.available(1), 10);
+        assert!(warehouse.pick_path(&[(2, 6)], PickOrder::Fifo).is_err());
+    }
+
+    #[test]
+    fn test_pick_path_adds_up_lines_for_one_item() {
+        let mut warehouse = warehouse("capacity 5\nrow 3");
+        let bolts = item(1, 5, Quality::Normal);
+        warehouse.add_zone(bolts.clone(), 0, 0, 0).unwrap();
+        warehouse.add_zone(bolts, 0, 0, 2).unwrap();
+
+        let path = warehouse
+            .pick_path(&[(1, 4), (1, 4)], PickOrder::Fifo)
+            .unwrap();
+        assert_eq!(
+            path.picks,
+            vec![
+                (
+                    1,
+                    Placement {
+                        location: Location::new(0, 0, 0),
+                        quantity: 5
+                    }
+                ),
+                (
+                    1,
+                    Placement {
+                        location: Location::new(0, 0, 2),
+                        quantity: 3
+                    }
+                ),
+            ]
+        );
+        assert_eq!(
+            warehouse.pick_path(&[(1, 6), (1, 6)], PickOrder::Fifo),
+            Err(WarehouseError::NotEnoughStock {
+                id: 1,
+                requested: 12,
+                available: 10
+            })
+        );
+        assert_eq!(
+            warehouse.pick_path(&[(1, u32::MAX), (1, 1)], PickOrder::Fifo),
+            Err(WarehouseError::NotEnoughStock {
+                id: 1,
+                requested: u32::MAX as u64 + 1,
+                available: 10
+            })
+        );
+    }
+}
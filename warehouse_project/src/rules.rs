@@ -0,0 +1,251 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       rules.rs
+ * Purpose:    Reads the placement rules of a site from a rules file and
+ *             turns them into the filters new items go through.
+ * =======================================================================
+ */
+
+use crate::filter::{
+    default_filters, And, ExpirationFilter, Filter, ForQuality, ForbiddenName, MaxQuantity, MaxRow,
+    MinDaysToExpiry, Named, Not, Or,
+};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const DEFAULT_RULES_PATH: &str = "rules.txt";
+
+/*
+ * Rules file format, one rule per line ('#' starts a comment). An item is
+ * stored only when it passes every rule.
+ *
+ *   max-row 2                   fragile items may not ask for a row above 2
+ *   min-days-to-expiry 3        fragile items need 3 days before expiry
+ *   not-expired                 expired items are refused
+ *   forbid-name Bleach          no item with this name (ignoring case)
+ *   max-quantity 500            at most 500 units per delivery
+ *   fragile max-quantity 50     a rule that only applies to one quality
+ *   not max-quantity 10         'not' inverts a rule: bulk deliveries only
+ *   any A | B & C               passes when A passes, or both B and C do
+ *   cold chain: <rule>          names the rule in rejection messages
+ */
+
+#[derive(Debug)]
+pub enum RuleError {
+    Io(io::Error),
+    Malformed { line: usize, reason: String },
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleError::Io(e) => write!(f, "could not read rules file: {}", e),
+            RuleError::Malformed { line, reason } => {
+                write!(f, "rules line {}: {}", line, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+const QUALITIES: [&str; 3] = ["normal", "fragile", "oversized"];
+
+pub fn parse(text: &str) -> Result<Vec<Box<dyn Filter>>, RuleError> {
+    let mut filters = vec![];
+
+    for (index, raw) in text.lines().enumerate() {
+        let content = raw.split('#').next().unwrap_or("").trim();
+        if content.is_empty() {
+            continue;
+        }
+
+        let filter = parse_line(content).map_err(|reason| RuleError::Malformed {
+            line: index + 1,
+            reason,
+        })?;
+        filters.push(filter);
+    }
+
+    Ok(filters)
+}
+
+pub fn load(path: &Path) -> Result<Vec<Box<dyn Filter>>, RuleError> {
+    let text = fs::read_to_string(path).map_err(RuleError::Io)?;
+    parse(&text)
+}
+
+// Uses the given file, else rules.txt when present, else the built-in rules.
+pub fn resolve(path: Option<&Path>) -> Result<Vec<Box<dyn Filter>>, RuleError> {
+    match path {
+        Some(path) => load(path),
+        None if Path::new(DEFAULT_RULES_PATH).exists() => load(Path::new(DEFAULT_RULES_PATH)),
+        None => Ok(default_filters()),
+    }
+}
+
+fn parse_line(content: &str) -> Result<Box<dyn Filter>, String> {
+    let (label, body) = match content.split_once(':') {
+        Some((label, body)) => (Some(label.trim()), body.trim()),
+        None => (None, content),
+    };
+
+    let filter = match body.strip_prefix("any ") {
+        Some(rest) => {
+            let alternatives = rest
+                .split('|')
+                .map(parse_all)
+                .collect::<Result<Vec<_>, String>>()?;
+            if alternatives.len() < 2 {
+                return Err("any expects: any <rule> | <rule> ...".to_string());
+            }
+            Box::new(Or::new(alternatives))
+        }
+        None => parse_all(body)?,
+    };
+
+    match label {
+        Some("") => Err("the rule name before ':' is empty".to_string()),
+        Some(label) => Ok(Box::new(Named::new(label, filter))),
+        None => Ok(filter),
+    }
+}
+
+// One or more rules joined by '&'.
+fn parse_all(text: &str) -> Result<Box<dyn Filter>, String> {
+    let mut filters = text
+        .split('&')
+        .map(|part| parse_rule(&part.split_whitespace().collect::<Vec<&str>>()))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if filters.len() == 1 {
+        Ok(filters.remove(0))
+    } else {
+        Ok(Box::new(And::new(filters)))
+    }
+}
+
+fn parse_rule(words: &[&str]) -> Result<Box<dyn Filter>, String> {
+    let number = |word: &str| {
+        word.parse::<u32>()
+            .map_err(|_| format!("'{}' is not a number", word))
+    };
+
+    match words {
+        [] => Err("missing rule".to_string()),
+        ["not", rest @ ..] => Ok(Box::new(Not::new(parse_rule(rest)?))),
+        [quality, rest @ ..] if QUALITIES.contains(quality) => {
+            Ok(Box::new(ForQuality::new(quality, parse_rule(rest)?)))
+        }
+        ["max-row", n] => Ok(Box::new(MaxRow::new(number(n)? as usize))),
+        ["min-days-to-expiry", n] => Ok(Box::new(MinDaysToExpiry {
+            days: number(n)? as i64,
+        })),
+        ["not-expired"] => Ok(Box::new(ExpirationFilter::new())),
+        ["forbid-name", name @ ..] if !name.is_empty() => Ok(Box::new(ForbiddenName {
+            name: name.join(" "),
+        })),
+        ["max-quantity", n] => Ok(Box::new(MaxQuantity { max: number(n)? })),
+        [keyword, ..] if is_rule(keyword) => Err(format!("wrong arguments for {}", keyword)),
+        [other, ..] => Err(format!("unknown rule '{}'", other)),
+    }
+}
+
+fn is_rule(keyword: &str) -> bool {
+    matches!(
+        keyword,
+        "max-row" | "min-days-to-expiry" | "not-expired" | "forbid-name" | "max-quantity"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{parse_date, FixedClock};
+    use crate::item::{Item, Quality};
+    use crate::layout::Layout;
+    use crate::strategy::Closest;
+    use crate::warehouse::Warehouse;
+    use chrono::DateTime;
+
+    fn item(name: &str, quantity: u32, quality: Quality) -> Item {
+        Item::new(1, name.to_string(), quantity, quality, DateTime::UNIX_EPOCH)
+    }
+
+    fn fragile(quantity: u32, expiry_date: &str) -> Item {
+        let quality = Quality::Fragile {
+            expiry_date: parse_date(expiry_date).unwrap(),
+            max_shelf: 0,
+        };
+        item("Milk", quantity, quality)
+    }
+
+    // The name of the first rule that refuses the item, if any.
+    fn refused_by(filters: &[Box<dyn Filter>], item: &Item) -> Option<String> {
+        let mut warehouse = Warehouse::from_layout(&Layout::default(), Box::new(Closest));
+        warehouse.set_clock(Box::new(FixedClock::on(parse_date("2030-01-10").unwrap())));
+        filters
+            .iter()
+            .find_map(|filter| filter.apply(&warehouse, item).err())
+            .map(|rejection| rejection.filter)
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        let text = "\
+# site B
+forbid-name Bleach
+fragile max-quantity 50     # small batches only
+cold chain: any not-expired & min-days-to-expiry 3 | max-quantity 5
+";
+        let filters = parse(text).unwrap();
+        assert_eq!(filters.len(), 3);
+
+        assert_eq!(
+            refused_by(&filters, &item("bleach", 1, Quality::Normal)),
+            Some("forbidden name".to_string())
+        );
+        assert_eq!(
+            refused_by(&filters, &item("Soap", 80, Quality::Normal)),
+            None
+        );
+        assert_eq!(
+            refused_by(&filters, &fragile(80, "2030-01-13")),
+            Some("fragile max quantity".to_string())
+        );
+        assert_eq!(refused_by(&filters, &fragile(10, "2030-01-13")), None);
+        assert_eq!(
+            refused_by(&filters, &fragile(10, "2030-01-12")),
+            Some("cold chain".to_string())
+        );
+    }
+
+    #[test]
+    fn test_empty_file_has_no_rules() {
+        assert!(parse("# nothing yet\n\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_parse_errors_name_the_line() {
+        let cases = [
+            ("max-row 2\nmax-row\n", 2),
+            ("max-row two", 1),
+            ("\n\nstack-height 3", 3),
+            ("any max-row 2", 1),
+            ("fragile", 1),
+            (": max-row 2", 1),
+            ("max-row 1 & ", 1),
+        ];
+        for (text, line) in cases {
+            match parse(text) {
+                Err(RuleError::Malformed { line: found, .. }) => {
+                    assert_eq!(found, line, "{}", text)
+                }
+                _ => panic!("'{}' should not parse", text),
+            }
+        }
+    }
+}
@@ -0,0 +1,313 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       storage.rs
+ * Purpose:    Saves and loads the warehouse to a versioned JSON
+ *             snapshot on disk.
+ * =======================================================================
+ */
+
+use crate::journal::{self, Journal, JournalError, Recovery};
+use crate::warehouse::Warehouse;
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const SNAPSHOT_VERSION: u32 = 3;
+pub const DEFAULT_SNAPSHOT_PATH: &str = "warehouse.json";
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(io::Error),
+    Corrupt(String),
+    IncompatibleVersion { found: u32, expected: u32 },
+    Journal(JournalError),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::Io(e) => write!(f, "could not access snapshot file: {}", e),
+            StorageError::Corrupt(msg) => write!(f, "snapshot file is corrupt: {}", msg),
+            StorageError::IncompatibleVersion { found, expected } => write!(
+                f,
+                "snapshot version {} is not supported (expected version {})",
+                found, expected
+            ),
+            StorageError::Journal(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<io::Error> for StorageError {
+    fn from(e: io::Error) -> Self {
+        StorageError::Io(e)
+    }
+}
+
+impl From<JournalError> for StorageError {
+    fn from(e: JournalError) -> Self {
+        StorageError::Journal(e)
+    }
+}
+
+#[derive(Serialize)]
+struct SnapshotOut<'a> {
+    version: u32,
+    warehouse: &'a Warehouse,
+}
+
+#[derive(Deserialize)]
+struct SnapshotIn {
+    version: u32,
+    warehouse: Value,
+}
+
+pub fn save(warehouse: &Warehouse, path: &Path) -> Result<(), StorageError> {
+    let snapshot = SnapshotOut {
+        version: SNAPSHOT_VERSION,
+        warehouse,
+    };
+    let json = serde_json::to_string_pretty(&snapshot)
+        .map_err(|e| StorageError::Corrupt(e.to_string()))?;
+
+    // Write next to the target and rename, so a crash never leaves half a file.
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, json)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+pub fn load(path: &Path) -> Result<Option<Warehouse>, StorageError> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(StorageError::Io(e)),
+    };
+
+    let snapshot: SnapshotIn =
+        serde_json::from_str(&text).map_err(|e| StorageError::Corrupt(e.to_string()))?;
+
+    let mut value = snapshot.warehouse;
+    let mut version = snapshot.version;
+    while version < SNAPSHOT_VERSION {
+        value = migrate(version, value)?;
+        version += 1;
+    }
+
+    if version != SNAPSHOT_VERSION {
+        return Err(StorageError::IncompatibleVersion {
+            found: snapshot.version,
+            expected: SNAPSHOT_VERSION,
+        });
+    }
+
+    let mut warehouse: Warehouse =
+        serde_json::from_value(value).map_err(|e| StorageError::Corrupt(e.to_string()))?;
+    warehouse.rebuild_index();
+    Ok(Some(warehouse))
+}
+
+// Upgrades a snapshot body from `version` to `version + 1`.
+fn migrate(version: u32, mut warehouse: Value) -> Result<Value, StorageError> {
+    let fields = warehouse
+        .as_object_mut()
+        .ok_or_else(|| StorageError::Corrupt("warehouse is not an object".to_string()))?;
+
+    match version {
+        // v1 kept a closed strategy enum plus the round-robin cursor.
+        1 => {
+            let next = fields.remove("last_used_index").unwrap_or(json!(0));
+            let strategy = match fields.remove("allocation_strategy") {
+                Some(Value::String(name)) if name == "Robin" => {
+                    json!({ "RoundRobin": { "next": next } })
+                }
+                _ => json!("Closest"),
+            };
+            fields.insert("strategy".to_string(), strategy);
+        }
+        // v2 kept receipt times as Unix seconds in a string.
+        2 => {
+            for item in stored_items(fields) {
+                let text = item.get("timestamp").and_then(Value::as_str).unwrap_or("");
+                let seconds = text.parse::<i64>().map_err(|_| {
+                    StorageError::Corrupt(format!("'{}' is not a Unix timestamp", text))
+                })?;
+                let time = DateTime::from_timestamp(seconds, 0).ok_or_else(|| {
+                    StorageError::Corrupt(format!("timestamp {} is out of range", seconds))
+                })?;
+                item.insert("timestamp".to_string(), json!(time));
+            }
+        }
+        _ => {
+            return Err(StorageError::IncompatibleVersion {
+                found: version,
+                expected: SNAPSHOT_VERSION,
+            })
+        }
+    }
+
+    Ok(warehouse)
+}
+
+// Every item object in a snapshot's rows.
+fn stored_items(warehouse: &mut Map<String, Value>) -> Vec<&mut Map<String, Value>> {
+    let zones = warehouse
+        .get_mut("rows")
+        .and_then(Value::as_array_mut)
+        .into_iter()
+        .flatten()
+        .filter_map(|row| row.get_mut("shelves")?.as_array_mut())
+        .flatten()
+        .filter_map(|shelf| shelf.get_mut("zones")?.as_array_mut())
+        .flatten();
+    zones
+        .filter_map(|zone| zone.get_mut("item")?.as_object_mut())
+        .collect()
+}
+
+// The journal lives next to the snapshot: warehouse.json -> warehouse.journal.
+pub fn journal_path(snapshot_path: &Path) -> PathBuf {
+    snapshot_path.with_extension("journal")
+}
+
+// Loads the snapshot (or builds a fresh warehouse when there is none),
+// replays the journal on top of it and keeps journaling from there on.
+pub fn open(
+    path: &Path,
+    fresh: impl FnOnce() -> Warehouse,
+) -> Result<(Warehouse, Recovery), StorageError> {
+    let mut warehouse = load(path)?.unwrap_or_else(fresh);
+    let journal_path = journal_path(path);
+    let recovery = journal::replay(&journal_path, &mut warehouse)?;
+    warehouse.attach_journal(Journal::open(&journal_path)?);
+    Ok((warehouse, recovery))
+}
+
+// Writes a snapshot and empties the journal, whose events it now contains.
+pub fn checkpoint(warehouse: &mut Warehouse, path: &Path) -> Result<(), StorageError> {
+    save(warehouse, path)?;
+    if let Some(journal) = warehouse.journal_mut() {
+        journal.clear()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::parse_date;
+    use crate::item::{Item, Quality};
+    use crate::layout::Layout;
+    use crate::strategy::Closest;
+    use crate::warehouse::Location;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("warehouse_{}_{}.json", name, std::process::id()))
+    }
+
+    fn small_warehouse() -> Warehouse {
+        Warehouse::from_layout(&Layout::parse("row 2").unwrap(), Box::new(Closest))
+    }
+
+    #[test]
+    fn test_save_and_load_round_trip() {
+        let path = temp_path("round_trip");
+        let mut warehouse = small_warehouse();
+        let quality = Quality::Fragile {
+            expiry_date: parse_date("2030-01-01").unwrap(),
+            max_shelf: 1,
+        };
+        let item = Item::new(7, "Glass".to_string(), 3, quality, DateTime::UNIX_EPOCH);
+        warehouse.add_zone(item, 0, 0, 1).unwrap();
+
+        save(&warehouse, &path).unwrap();
+        let loaded = load(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.find_by_id(7), vec![Location::new(0, 0, 1)]);
+        let zones = &loaded.rows[0].shelves[0].zones;
+        assert!(zones[0].item.is_none());
+        let item = zones[1].item.as_ref().unwrap();
+        assert_eq!(item.id, 7);
+        assert_eq!(item.name, "Glass");
+        assert!(matches!(
+            item.quality,
+            Quality::Fragile { max_shelf: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn test_load_missing_file() {
+        let path = temp_path("missing");
+        assert!(load(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_load_corrupt_and_wrong_version() {
+        let path = temp_path("corrupt");
+        fs::write(&path, "{ not json").unwrap();
+        assert!(matches!(load(&path), Err(StorageError::Corrupt(_))));
+
+        fs::write(&path, r#"{"version": 0, "warehouse": {}}"#).unwrap();
+        assert!(matches!(
+            load(&path),
+            Err(StorageError::IncompatibleVersion { found: 0, .. })
+        ));
+
+        fs::write(&path, r#"{"version": 99, "warehouse": {}}"#).unwrap();
+        assert!(matches!(
+            load(&path),
+            Err(StorageError::IncompatibleVersion { found: 99, .. })
+        ));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_migrates_version_1() {
+        let path = temp_path("v1");
+        let v1 = r#"{"version": 1, "warehouse": {
+            "rows": [{"shelves": [{"zones": [{"item": null}, {"item": null}]}]}],
+            "allocation_strategy": "Robin",
+            "last_used_index": 1
+        }}"#;
+        fs::write(&path, v1).unwrap();
+
+        let loaded = load(&path).unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            loaded.strategy_spec(),
+            crate::strategy::StrategySpec::RoundRobin { next: 1 }
+        );
+        assert_eq!(loaded.rows[0].shelves[0].zones.len(), 2);
+    }
+
+    #[test]
+    fn test_load_migrates_version_2_timestamps() {
+        let path = temp_path("v2");
+        let v2 = r#"{"version": 2, "warehouse": {
+            "rows": [{"shelves": [{"zones": [{"item": {
+                "id": 4, "name": "Milk", "quantity": 2, "timestamp": "86400",
+                "quality": {"Fragile": {"expiry_date": "2030-01-01", "max_shelf": 1}}
+            }}]}]}],
+            "strategy": "Closest"
+        }}"#;
+        fs::write(&path, v2).unwrap();
+
+        let loaded = load(&path).unwrap().unwrap();
+        let item = loaded.rows[0].shelves[0].zones[0].item.as_ref().unwrap();
+        assert_eq!(item.timestamp, DateTime::from_timestamp(86400, 0).unwrap());
+        assert_eq!(item.expiry_date(), Some(parse_date("2030-01-01").unwrap()));
+
+        fs::write(&path, v2.replace("86400", "yesterday")).unwrap();
+        assert!(matches!(load(&path), Err(StorageError::Corrupt(_))));
+        fs::remove_file(&path).unwrap();
+    }
+}
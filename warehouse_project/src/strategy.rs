@@ -0,0 +1,338 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       strategy.rs
+ * Purpose:    Allocation strategies that choose the zone a new item is
+ *             stored in, and the constraints they must respect.
+ * =======================================================================
+ */
+
+use crate::item::{Item, Quality};
+use crate::warehouse::{Location, Warehouse};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fmt;
+
+// Where an item is allowed to go, derived from its quality.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Constraints {
+    pub zones_needed: usize,
+    pub max_row: Option<usize>,
+}
+
+impl Constraints {
+    pub fn for_item(item: &Item) -> Constraints {
+        let max_row = match &item.quality {
+            Quality::Fragile { max_shelf, .. } => Some(*max_shelf as usize),
+            _ => None,
+        };
+
+        Constraints {
+            zones_needed: item.zones_needed(),
+            max_row,
+        }
+    }
+
+    pub fn allows(&self, loc: Location) -> bool {
+        self.max_row.is_none_or(|max_row| loc.row <= max_row)
+    }
+}
+
+pub trait AllocationStrategy {
+    // Picks where the item goes, among the places `constraints` allow.
+    // Warehouse::candidates lists them in grid order.
+    fn find_zone(
+        &mut self,
+        warehouse: &Warehouse,
+        item: &Item,
+        constraints: &Constraints,
+    ) -> Option<Location>;
+
+    // How the strategy is written to a snapshot. Strategies defined outside
+    // this module are saved by name and come back as Closest.
+    fn spec(&self) -> StrategySpec;
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum StrategySpec {
+    Closest,
+    RoundRobin { next: usize },
+    EmptiestShelf,
+    GroupByName,
+    SeededRandom { seed: u64, state: u64 },
+    Custom(String),
+}
+
+impl StrategySpec {
+    // Accepts: closest, robin, emptiest, group, random[:SEED].
+    pub fn parse(text: &str) -> Result<StrategySpec, String> {
+        let (name, arg) = match text.split_once(':') {
+            Some((name, arg)) => (name, Some(arg)),
+            None => (text, None),
+        };
+
+        match (name.to_lowercase().as_str(), arg) {
+            ("closest", None) => Ok(StrategySpec::Closest),
+            ("robin", None) => Ok(StrategySpec::RoundRobin { next: 0 }),
+            ("emptiest", None) => Ok(StrategySpec::EmptiestShelf),
+            ("group", None) => Ok(StrategySpec::GroupByName),
+            ("random", None) => Ok(StrategySpec::SeededRandom { seed: 0, state: 0 }),
+            ("random", Some(seed)) => seed
+                .parse()
+                .map(|seed| StrategySpec::SeededRandom { seed, state: seed })
+                .map_err(|_| format!("'{}' is not a valid random seed", seed)),
+            _ => Err(format!("unknown allocation strategy '{}'", text)),
+        }
+    }
+
+    pub fn build(&self) -> Box<dyn AllocationStrategy> {
+        match self {
+            StrategySpec::Closest | StrategySpec::Custom(_) => Box::new(Closest),
+            StrategySpec::RoundRobin { next } => Box::new(RoundRobin { next: *next }),
+            StrategySpec::EmptiestShelf => Box::new(EmptiestShelf),
+            StrategySpec::GroupByName => Box::new(GroupByName),
+            StrategySpec::SeededRandom { seed, state } => {
+                let mut random = SeededRandom::new(*seed);
+                random.state = *state;
+                Box::new(random)
+            }
+        }
+    }
+}
+
+impl fmt::Display for StrategySpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StrategySpec::Closest => write!(f, "closest"),
+            StrategySpec::RoundRobin { .. } => write!(f, "robin"),
+            StrategySpec::EmptiestShelf => write!(f, "emptiest"),
+            StrategySpec::GroupByName => write!(f, "group"),
+            StrategySpec::SeededRandom { seed, .. } => write!(f, "random:{}", seed),
+            StrategySpec::Custom(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+// First free place in row, shelf, zone order.
+pub struct Closest;
+
+impl AllocationStrategy for Closest {
+    fn find_zone(
+        &mut self,
+        warehouse: &Warehouse,
+        _item: &Item,
+        c: &Constraints,
+    ) -> Option<Location> {
+        warehouse.candidates(c).into_iter().next()
+    }
+
+    fn spec(&self) -> StrategySpec {
+        StrategySpec::Closest
+    }
+}
+
+// Walks the grid from where the previous item was stored.
+pub struct RoundRobin {
+    pub next: usize,
+}
+
+impl AllocationStrategy for RoundRobin {
+    fn find_zone(
+        &mut self,
+        warehouse: &Warehouse,
+        _item: &Item,
+        c: &Constraints,
+    ) -> Option<Location> {
+        let all_zones = warehouse.all_locations();
+        let total = all_zones.len();
+
+        for i in 0..total {
+            let index = (self.next + i) % total;
+            let loc = all_zones[index];
+
+            if c.allows(loc) && warehouse.span_is_free(loc, c.zones_needed) {
+                self.next = (index + c.zones_needed) % total;
+                return Some(loc);
+            }
+        }
+        None
+    }
+
+    fn spec(&self) -> StrategySpec {
+        StrategySpec::RoundRobin { next: self.next }
+    }
+}
+
+// Spreads stock out by filling the shelf with the most free zones first.
+pub struct EmptiestShelf;
+
+impl AllocationStrategy for EmptiestShelf {
+    fn find_zone(
+        &mut self,
+        warehouse: &Warehouse,
+        _item: &Item,
+        c: &Constraints,
+    ) -> Option<Location> {
+        warehouse
+            .candidates(c)
+            .into_iter()
+            .min_by_key(|loc| Reverse(warehouse.free_zones(loc.row, loc.shelf)))
+    }
+
+    fn spec(&self) -> StrategySpec {
+        StrategySpec::EmptiestShelf
+    }
+}
+
+// Keeps stock of the same item together: the free place nearest to an
+// existing one with the same name, preferring the same shelf, then row.
+pub struct GroupByName;
+
+impl AllocationStrategy for GroupByName {
+    fn find_zone(
+        &mut self,
+        warehouse: &Warehouse,
+        item: &Item,
+        c: &Constraints,
+    ) -> Option<Location> {
+        let stock = warehouse.find_by_name(&item.name);
+
+        let distance = |loc: &Location| {
+            stock
+                .iter()
+                .map(|s| {
+                    (
+                        s.row.abs_diff(loc.row),
+                        s.shelf.abs_diff(loc.shelf),
+                        s.zone.abs_diff(loc.zone),
+                    )
+                })
+                .min()
+        };
+
+        // With no stock yet every distance is None and the first place wins.
+        warehouse.candidates(c).into_iter().min_by_key(distance)
+    }
+
+    fn spec(&self) -> StrategySpec {
+        StrategySpec::GroupByName
+    }
+}
+
+// Picks any free place; the same seed always gives the same sequence.
+pub struct SeededRandom {
+    pub seed: u64,
+    pub state: u64,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> SeededRandom {
+        SeededRandom { seed, state: seed }
+    }
+
+    // splitmix64
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+impl AllocationStrategy for SeededRandom {
+    fn find_zone(
+        &mut self,
+        warehouse: &Warehouse,
+        _item: &Item,
+        c: &Constraints,
+    ) -> Option<Location> {
+        let candidates = warehouse.candidates(c);
+        if candidates.is_empty() {
+            return None;
+        }
+        let index = (self.next_u64() % candidates.len() as u64) as usize;
+        Some(candidates[index])
+    }
+
+    fn spec(&self) -> StrategySpec {
+        StrategySpec::SeededRandom {
+            seed: self.seed,
+            state: self.state,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{place, warehouse_with};
+    use chrono::DateTime;
+
+    fn item(id: u32, name: &str) -> Item {
+        Item::new(
+            id,
+            name.to_string(),
+            1,
+            Quality::Normal,
+            DateTime::UNIX_EPOCH,
+        )
+    }
+
+    #[test]
+    fn test_emptiest_shelf_spreads_items() {
+        let mut warehouse = warehouse_with(Box::new(EmptiestShelf), "row 2 3");
+        let first = place(&mut warehouse, item(1, "A")).unwrap();
+        let second = place(&mut warehouse, item(2, "B")).unwrap();
+        let third = place(&mut warehouse, item(3, "C")).unwrap();
+
+        assert_eq!(first, Location::new(0, 1, 0));
+        assert_eq!(second, Location::new(0, 0, 0));
+        assert_eq!(third, Location::new(0, 1, 1));
+    }
+
+    #[test]
+    fn test_group_by_name_places_next_to_stock() {
+        let mut warehouse = warehouse_with(Box::new(GroupByName), "grid 2 1 3");
+        warehouse.add_zone(item(1, "Bolts"), 1, 0, 1).unwrap();
+
+        assert_eq!(
+            place(&mut warehouse, item(2, "Nuts")),
+            Ok(Location::new(0, 0, 0))
+        );
+        let near = place(&mut warehouse, item(3, "bolts")).unwrap();
+        assert_eq!(near.row, 1);
+        assert_eq!(near.zone.abs_diff(1), 1);
+    }
+
+    #[test]
+    fn test_seeded_random_is_repeatable() {
+        let placements = |seed| {
+            let mut warehouse = warehouse_with(Box::new(SeededRandom::new(seed)), "grid 2 2 3");
+            (0..6)
+                .map(|id| place(&mut warehouse, item(id, "X")).unwrap())
+                .collect::<Vec<Location>>()
+        };
+
+        assert_eq!(placements(7), placements(7));
+        assert_ne!(placements(7), placements(8));
+    }
+
+    #[test]
+    fn test_spec_parse_and_round_trip() {
+        assert_eq!(StrategySpec::parse("Closest"), Ok(StrategySpec::Closest));
+        assert_eq!(
+            StrategySpec::parse("random:5"),
+            Ok(StrategySpec::SeededRandom { seed: 5, state: 5 })
+        );
+        assert!(StrategySpec::parse("random:x").is_err());
+        assert!(StrategySpec::parse("fastest").is_err());
+
+        let mut robin = RoundRobin { next: 0 };
+        let warehouse = warehouse_with(Box::new(Closest), "row 3");
+        let c = Constraints::for_item(&item(1, "A"));
+        robin.find_zone(&warehouse, &item(1, "A"), &c);
+        assert_eq!(robin.spec(), StrategySpec::RoundRobin { next: 1 });
+        assert_eq!(robin.spec().build().spec(), robin.spec());
+    }
+}
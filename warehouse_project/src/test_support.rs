@@ -0,0 +1,45 @@
+/*
+ * =======================================================================
+ * Author:     Rita Ferreira
+ * File:       test_support.rs
+ * Purpose:    Warehouses and items the unit tests of every module build
+ *             their cases from.
+ * =======================================================================
+ */
+
+use crate::clock::{parse_date, FixedClock};
+use crate::item::{Item, Quality};
+use crate::layout::Layout;
+use crate::strategy::{AllocationStrategy, Closest};
+use crate::warehouse::{Location, Warehouse, WarehouseError};
+use chrono::DateTime;
+
+pub fn warehouse_with(strategy: Box<dyn AllocationStrategy>, layout: &str) -> Warehouse {
+    Warehouse::from_layout(&Layout::parse(layout).unwrap(), strategy)
+}
+
+// Where the (single-zone) item ended up.
+pub fn place(warehouse: &mut Warehouse, item: Item) -> Result<Location, WarehouseError> {
+    warehouse
+        .store(item, &[])
+        .map(|placements| placements[0].location)
+}
+
+// A warehouse with the closest-zone strategy and "today" pinned to
+// 2030-01-10.
+pub fn warehouse(layout: &str) -> Warehouse {
+    let mut warehouse = warehouse_with(Box::new(Closest), layout);
+    warehouse.set_clock(Box::new(FixedClock::on(parse_date("2030-01-10").unwrap())));
+    warehouse
+}
+
+// `quantity` units of "Item <id>", received at the epoch.
+pub fn item(id: u32, quantity: u32, quality: Quality) -> Item {
+    Item::new(
+        id,
+        format!("Item {}", id),
+        quantity,
+        quality,
+        DateTime::UNIX_EPOCH,
+    )
+}
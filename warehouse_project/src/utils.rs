@@ -2,28 +2,91 @@
  * =======================================================================
  * Author:     Rita Ferreira
  * File:       utils.rs
- * Purpose:    Utility functions for console input and number parsing
- *             with validation.
+ * Purpose:    Console input and output for the menu, with number and date
+ *             parsing and validation, so the menu can also be run from a
+ *             script.
  * =======================================================================
  */
 
-use std::io::{self, Write};
+use chrono::NaiveDate;
+use std::io::{BufRead, Write};
+use warehouse::clock::parse_date;
 
-pub fn read_input(text: &str) -> String {
-    print!("{}", text);
-    io::stdout().flush().unwrap();
+// Where the menu reads what is typed and writes what it shows: the terminal,
+// a script file, or text held in memory by the tests.
+pub trait Console {
+    // The next line without its line ending, or None once the input ends.
+    fn read_line(&mut self) -> Option<String>;
 
-    let mut input = String::new();
-    io::stdin().read_line(&mut input).unwrap();
-    input.trim().to_string()
+    fn write(&mut self, text: &str);
+
+    fn write_line(&mut self, line: &str) {
+        self.write(line);
+        self.write("\n");
+    }
+}
+
+pub struct IoConsole<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> IoConsole<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        IoConsole { input, output }
+    }
+}
+
+impl<R: BufRead, W: Write> Console for IoConsole<R, W> {
+    fn read_line(&mut self) -> Option<String> {
+        let mut line = String::new();
+        match self.input.read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line.trim_end_matches(['\r', '\n']).to_string()),
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        // A closed output is no reason to stop; the input decides that.
+        let _ = self.output.write_all(text.as_bytes());
+        let _ = self.output.flush();
+    }
 }
 
-pub fn parse_input(text: &str) -> usize {
+// The input ended while the menu was still asking for something.
+#[derive(Debug, PartialEq)]
+pub struct Eof;
+
+pub fn read_input(console: &mut dyn Console, text: &str) -> Result<String, Eof> {
+    console.write(text);
+    match console.read_line() {
+        Some(input) => Ok(input.trim().to_string()),
+        None => Err(Eof),
+    }
+}
+
+pub fn parse_input(console: &mut dyn Console, text: &str) -> Result<usize, Eof> {
     loop {
-        let input = read_input(text);
+        let input = read_input(console, text)?;
         match input.parse::<usize>() {
-            Ok(value) => return value,
-            Err(_) => println!("Please enter a valid number."),
+            Ok(value) => return Ok(value),
+            Err(_) => console.write_line("Please enter a valid number."),
+        }
+    }
+}
+
+pub fn parse_date_input(console: &mut dyn Console, text: &str) -> Result<NaiveDate, Eof> {
+    loop {
+        let input = read_input(console, text)?;
+        match parse_date(&input) {
+            Ok(date) => return Ok(date),
+            Err(e) => console.write_line(&format!("Please enter a valid date: {}.", e)),
         }
     }
 }
+
+pub fn print_lines(console: &mut dyn Console, lines: &[String]) {
+    for line in lines {
+        console.write_line(line);
+    }
+}
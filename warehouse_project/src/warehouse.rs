@@ -7,81 +7,1190 @@
  * ===========================================================================
  */
 
+use crate::clock::{Clock, SystemClock};
+use crate::filter::{Filter, Rejection};
+use crate::index::ItemIndex;
 use crate::item::Item;
+use crate::journal::{Event, Journal};
+use crate::layout::Layout;
+use crate::orders::{OrderBook, OrderStatus};
+use crate::route::{DistanceModel, RouteStyle};
+use crate::strategy::{AllocationStrategy, Closest, Constraints, StrategySpec};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
 
-pub enum AllocationStrategy {
-    Closest,
-    Robin,
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Location {
+    pub row: usize,
+    pub shelf: usize,
+    pub zone: usize,
 }
 
+impl Location {
+    pub fn new(row: usize, shelf: usize, zone: usize) -> Location {
+        Location { row, shelf, zone }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Row {}, Shelf {}, Zone {}",
+            self.row, self.shelf, self.zone
+        )
+    }
+}
+
+// Where `store` put (part of) an item and how many units went there.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Placement {
+    pub location: Location,
+    pub quantity: u32,
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({} units)", self.location, self.quantity)
+    }
+}
+
+// The stock of one lot of an item. Deliveries without a lot number are
+// told apart by their expiry date.
+#[derive(Debug, PartialEq)]
+pub struct Lot {
+    pub lot: Option<String>,
+    pub expiry_date: Option<NaiveDate>,
+    // When the first units of the lot were received.
+    pub received: DateTime<Utc>,
+    pub units: u64,
+    pub locations: Vec<Location>,
+}
+
+impl fmt::Display for Lot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Lot {}", self.lot.as_deref().unwrap_or("-"))?;
+        if let Some(date) = self.expiry_date {
+            write!(f, ", expires {}", date)?;
+        }
+        write!(
+            f,
+            ", received {}: {} units in {} zones",
+            self.received.date_naive(),
+            self.units,
+            self.locations.len()
+        )
+    }
+}
+
+// One step of a move plan.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Move {
+    pub from: Location,
+    pub to: Location,
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} -> {}", self.from, self.to)
+    }
+}
+
+// Moves to review before `apply_plan` carries them out, in order.
+#[derive(Debug, PartialEq)]
+pub struct MovePlan {
+    pub moves: Vec<Move>,
+    // The warehouse's event_seq when the plan was made.
+    event_seq: u64,
+}
+
+// Which units `pick` takes first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PickOrder {
+    // Oldest received first.
+    Fifo,
+    // Soonest expiry first; items without an expiry date go last.
+    Fefo,
+}
+
+impl PickOrder {
+    pub fn parse(text: &str) -> Result<PickOrder, String> {
+        match text.to_lowercase().as_str() {
+            "fifo" => Ok(PickOrder::Fifo),
+            "fefo" => Ok(PickOrder::Fefo),
+            _ => Err(format!("unknown pick order '{}'", text)),
+        }
+    }
+}
+
+// How many days ahead expiry reports look unless told otherwise.
+pub const DEFAULT_EXPIRY_DAYS: i64 = 3;
+
+#[derive(Debug, PartialEq)]
+pub enum WarehouseError {
+    // The coordinates (or the end of an oversized span) are off the grid.
+    OutOfBounds(Location),
+    // Something already occupies a zone the item needs.
+    ZoneOccupied(Location),
+    // There is nothing stored at the zone.
+    ZoneEmpty(Location),
+    // Every zone (or run of zones) is taken, or the units do not fit.
+    NoCapacity,
+    // There is room, but not where the item's constraints allow it.
+    NoCompatibleZone,
+    // A filter turned the item away, and why.
+    Rejected(Rejection),
+    // The id is already used by a different product.
+    DuplicateId(u32),
+    // The lot is already stored with a different expiry date.
+    LotMismatch {
+        id: u32,
+        lot: String,
+    },
+    // The item is past its expiry date.
+    Expired(u32),
+    // Fewer units are stored than were asked for.
+    NotEnoughStock {
+        id: u32,
+        requested: u64,
+        available: u64,
+    },
+    // Zero units make no sense to store.
+    ZeroQuantity,
+    // The warehouse changed after the move plan was made.
+    StalePlan,
+    // No receipt or outbound order has this number.
+    UnknownReceipt(u32),
+    UnknownOrder(u32),
+    // The receipt or order was already completed or cancelled.
+    ReceiptClosed {
+        id: u32,
+        status: OrderStatus,
+    },
+    OrderClosed {
+        id: u32,
+        status: OrderStatus,
+    },
+    // A receipt or order must list something.
+    EmptyOrder,
+    // One line of a receipt could not be stored, so none of it was.
+    ReceiptRejected {
+        receipt: u32,
+        line: usize,
+        reason: Box<WarehouseError>,
+    },
+}
+
+impl fmt::Display for WarehouseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WarehouseError::OutOfBounds(loc) => write!(f, "{} is outside the warehouse.", loc),
+            WarehouseError::ZoneOccupied(loc) => write!(f, "{} is already occupied.", loc),
+            WarehouseError::ZoneEmpty(loc) => write!(f, "{} is empty.", loc),
+            WarehouseError::NoCapacity => write!(f, "No space available for the item."),
+            WarehouseError::NoCompatibleZone => {
+                write!(f, "No compatible zone available for the item.")
+            }
+            WarehouseError::Rejected(rejection) => write!(
+                f,
+                "Item rejected by the {} filter: {}.",
+                rejection.filter, rejection.reason
+            ),
+            WarehouseError::DuplicateId(id) => {
+                write!(f, "ID {} is already used by a different product.", id)
+            }
+            WarehouseError::LotMismatch { id, lot } => write!(
+                f,
+                "Lot {} of item {} is already stored with another expiry date.",
+                lot, id
+            ),
+            WarehouseError::Expired(id) => write!(f, "Item {} has already expired.", id),
+            WarehouseError::NotEnoughStock {
+                id,
+                requested,
+                available,
+            } => write!(
+                f,
+                "Not enough stock of item {}: {} requested, {} available.",
+                id, requested, available
+            ),
+            WarehouseError::ZeroQuantity => write!(f, "Quantity must be at least 1."),
+            WarehouseError::StalePlan => {
+                write!(f, "The warehouse changed since the plan was made.")
+            }
+            WarehouseError::UnknownReceipt(id) => write!(f, "There is no receipt {}.", id),
+            WarehouseError::UnknownOrder(id) => write!(f, "There is no order {}.", id),
+            WarehouseError::ReceiptClosed { id, status } => {
+                write!(f, "Receipt {} is already {}.", id, status)
+            }
+            WarehouseError::OrderClosed { id, status } => {
+                write!(f, "Order {} is already {}.", id, status)
+            }
+            WarehouseError::EmptyOrder => write!(f, "The order lists no items."),
+            WarehouseError::ReceiptRejected {
+                receipt,
+                line,
+                reason,
+            } => write!(
+                f,
+                "Receipt {} rejected at line {}, nothing was stored: {}",
+                receipt, line, reason
+            ),
+        }
+    }
+}
+
+impl std::error::Error for WarehouseError {}
+
+#[derive(Serialize, Deserialize)]
 pub struct Warehouse {
     pub rows: Vec<Row>,
-    allocation_strategy: AllocationStrategy,
-    last_used_index: usize,
+    #[serde(with = "strategy_serde")]
+    strategy: Box<dyn AllocationStrategy>,
+    #[serde(default)]
+    event_seq: u64,
+    #[serde(skip)]
+    journal: Option<Journal>,
+    // Rebuilt from the rows after loading, see rebuild_index.
+    #[serde(skip)]
+    index: ItemIndex,
+    #[serde(skip, default = "system_clock")]
+    clock: Box<dyn Clock>,
+    // Receipts and outbound orders, see orders.rs.
+    #[serde(default)]
+    pub(crate) orders: OrderBook,
+    // How pickers walk the grid, see route.rs.
+    #[serde(default)]
+    pub(crate) distance: DistanceModel,
+    #[serde(default)]
+    pub(crate) route_style: RouteStyle,
+}
+
+fn system_clock() -> Box<dyn Clock> {
+    Box::new(SystemClock)
 }
 
 impl Warehouse {
-    pub fn new(strategy: AllocationStrategy) -> Warehouse {
+    pub fn new(strategy: Box<dyn AllocationStrategy>) -> Warehouse {
         Warehouse {
             rows: Vec::new(),
-            allocation_strategy: strategy,
-            last_used_index: 0,
+            strategy,
+            event_seq: 0,
+            journal: None,
+            index: ItemIndex::default(),
+            clock: system_clock(),
+            orders: OrderBook::default(),
+            distance: DistanceModel::default(),
+            route_style: RouteStyle::default(),
         }
     }
 
+    pub fn from_layout(layout: &Layout, strategy: Box<dyn AllocationStrategy>) -> Warehouse {
+        let mut warehouse = Warehouse::new(strategy);
+        warehouse.distance = layout.distance;
+        warehouse.route_style = layout.route;
+
+        for shelves in &layout.rows {
+            let mut row = Row::new();
+            for zone_count in shelves {
+                let mut shelf = Shelf::new();
+                for _ in 0..*zone_count {
+                    shelf.add_zone(Zone::with_capacity(layout.zone_capacity));
+                }
+                row.add_shelf(shelf);
+            }
+            warehouse.add_row(row);
+        }
+
+        warehouse
+    }
+
+    pub fn set_strategy(&mut self, strategy: Box<dyn AllocationStrategy>) {
+        self.strategy = strategy;
+    }
+
+    pub fn strategy_spec(&self) -> StrategySpec {
+        self.strategy.spec()
+    }
+
+    // Replaces the system clock, e.g. to pin "today" in tests.
+    pub fn set_clock(&mut self, clock: Box<dyn Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn now(&self) -> DateTime<Utc> {
+        self.clock.now()
+    }
+
+    pub fn today(&self) -> NaiveDate {
+        self.clock.today()
+    }
+
+    pub fn attach_journal(&mut self, journal: Journal) {
+        self.journal = Some(journal);
+    }
+
+    pub fn journal_mut(&mut self) -> Option<&mut Journal> {
+        self.journal.as_mut()
+    }
+
+    pub fn event_seq(&self) -> u64 {
+        self.event_seq
+    }
+
+    pub fn set_event_seq(&mut self, seq: u64) {
+        self.event_seq = seq;
+    }
+
+    pub(crate) fn record(&mut self, event: Event) {
+        self.event_seq += 1;
+        if let Some(journal) = &mut self.journal {
+            if let Err(e) = journal.append(self.event_seq, &event) {
+                eprintln!("Warning: could not write to journal: {}", e);
+            }
+        }
+    }
+
+    // Indexes every stored item again; needed after the rows were replaced
+    // wholesale, as when loading a snapshot.
+    pub fn rebuild_index(&mut self) {
+        let mut index = ItemIndex::default();
+        for (loc, item) in self.items() {
+            index.insert(loc, item);
+        }
+        self.index = index;
+    }
+
+    pub fn contains(&self, row: usize, shelf: usize, zone: usize) -> bool {
+        row < self.rows.len()
+            && shelf < self.rows[row].shelves.len()
+            && zone < self.rows[row].shelves[shelf].zones.len()
+    }
+
     pub fn add_row(&mut self, row: Row) {
         self.rows.push(row);
     }
 
-    pub fn find_zone(&mut self, item: &Item) -> Option<(usize, usize, usize)> {
-        match self.allocation_strategy {
-            AllocationStrategy::Closest => self.find_closest(item),
-            AllocationStrategy::Robin => self.find_robin(item),
+    pub fn find_zone(&mut self, item: &Item) -> Result<Location, WarehouseError> {
+        let constraints = Constraints::for_item(item);
+
+        // The strategy looks at the warehouse while it updates its own state,
+        // so it is taken out for the duration of the call.
+        let mut strategy = std::mem::replace(&mut self.strategy, Box::new(Closest));
+        let found = strategy.find_zone(self, item, &constraints);
+        self.strategy = strategy;
+
+        found.ok_or_else(|| {
+            let unconstrained = Constraints {
+                max_row: None,
+                ..constraints
+            };
+            if self.candidates(&unconstrained).is_empty() {
+                WarehouseError::NoCapacity
+            } else {
+                WarehouseError::NoCompatibleZone
+            }
+        })
+    }
+
+    pub fn all_locations(&self) -> Vec<Location> {
+        let mut all_zones = vec![];
+
+        for (r_idx, row) in self.rows.iter().enumerate() {
+            for (s_idx, shelf) in row.shelves.iter().enumerate() {
+                for z_idx in 0..shelf.zones.len() {
+                    all_zones.push(Location::new(r_idx, s_idx, z_idx));
+                }
+            }
+        }
+
+        all_zones
+    }
+
+    // Every place an item with these constraints could start, in grid order.
+    pub fn candidates(&self, constraints: &Constraints) -> Vec<Location> {
+        self.all_locations()
+            .into_iter()
+            .filter(|loc| constraints.allows(*loc))
+            .filter(|loc| self.span_is_free(*loc, constraints.zones_needed))
+            .collect()
+    }
+
+    pub fn free_zones(&self, row: usize, shelf: usize) -> usize {
+        self.rows[row].shelves[shelf]
+            .zones
+            .iter()
+            .filter(|zone| zone.is_free())
+            .count()
+    }
+
+    // True when `count` zones starting at `loc` exist on its shelf and are free.
+    pub fn span_is_free(&self, loc: Location, count: usize) -> bool {
+        let zones = &self.rows[loc.row].shelves[loc.shelf].zones;
+        loc.zone + count <= zones.len()
+            && zones[loc.zone..loc.zone + count].iter().all(Zone::is_free)
+    }
+
+    // The zone at these coordinates, if the grid has one.
+    fn checked(&self, row: usize, shelf: usize, zone: usize) -> Result<Location, WarehouseError> {
+        let loc = Location::new(row, shelf, zone);
+        if self.contains(row, shelf, zone) {
+            Ok(loc)
+        } else {
+            Err(WarehouseError::OutOfBounds(loc))
+        }
+    }
+
+    // Like `checked`, but the zone must hold an item.
+    fn checked_item(&self, row: usize, shelf: usize, zone: usize) -> Result<&Item, WarehouseError> {
+        let loc = self.checked(row, shelf, zone)?;
+        self.zone(loc)
+            .item
+            .as_ref()
+            .ok_or(WarehouseError::ZoneEmpty(loc))
+    }
+
+    // The zone holding the item that occupies `loc`: `loc` itself, or the
+    // first zone of the oversized item it is reserved for.
+    fn head_of(&self, loc: Location) -> Result<Location, WarehouseError> {
+        self.checked(loc.row, loc.shelf, loc.zone)?;
+        let head = Location {
+            zone: self.zone(loc).reserved_by.unwrap_or(loc.zone),
+            ..loc
+        };
+        match self.zone(head).item {
+            Some(_) => Ok(head),
+            None => Err(WarehouseError::ZoneEmpty(loc)),
+        }
+    }
+
+    // Checks that `count` zones starting at `loc` exist and are free. Zones
+    // of the item stored at `moving` count as free, since it is leaving them.
+    fn check_span(
+        &self,
+        loc: Location,
+        count: usize,
+        moving: Option<Location>,
+    ) -> Result<(), WarehouseError> {
+        let zones = &self.rows[loc.row].shelves[loc.shelf].zones;
+        let end = loc.zone + count;
+        if end > zones.len() {
+            return Err(WarehouseError::OutOfBounds(Location {
+                zone: end - 1,
+                ..loc
+            }));
+        }
+
+        let leaving = |z: usize| {
+            moving.is_some_and(|head| {
+                head.row == loc.row
+                    && head.shelf == loc.shelf
+                    && (z == head.zone || zones[z].reserved_by == Some(head.zone))
+            })
+        };
+        match (loc.zone..end).find(|z| !zones[*z].is_free() && !leaving(*z)) {
+            Some(taken) => Err(WarehouseError::ZoneOccupied(Location {
+                zone: taken,
+                ..loc
+            })),
+            None => Ok(()),
+        }
+    }
+
+    // Refuses an item whose id is already stored for another product, or
+    // whose lot is stored with another expiry date.
+    fn check_identity(&self, item: &Item) -> Result<(), WarehouseError> {
+        let clashes = |loc: &Location| {
+            let stored = self.zone(*loc).item.as_ref();
+            stored.is_some_and(|stored| !stored.same_sku(item))
+        };
+        if self.find_by_id(item.id).iter().any(clashes) {
+            return Err(WarehouseError::DuplicateId(item.id));
+        }
+
+        let Some(lot) = &item.lot else {
+            return Ok(());
+        };
+        let other_expiry = |loc: &Location| {
+            let stored = self.zone(*loc).item.as_ref();
+            stored.is_some_and(|stored| {
+                stored.lot.as_ref() == Some(lot) && stored.expiry_date() != item.expiry_date()
+            })
+        };
+        if self.find_by_id(item.id).iter().any(other_expiry) {
+            return Err(WarehouseError::LotMismatch {
+                id: item.id,
+                lot: lot.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    // Puts the item at `loc` and reserves the rest of its span.
+    fn attach(&mut self, loc: Location, item: Item) {
+        self.index.insert(loc, &item);
+        let zones = &mut self.rows[loc.row].shelves[loc.shelf].zones;
+        for extra in &mut zones[loc.zone + 1..loc.zone + item.zones_needed()] {
+            extra.reserved_by = Some(loc.zone);
+        }
+        zones[loc.zone].item = Some(item);
+    }
+
+    // Takes the item out of `head` and frees the rest of its span.
+    fn detach(&mut self, head: Location) -> Item {
+        let zones = &mut self.rows[head.row].shelves[head.shelf].zones;
+        let item = zones[head.zone].item.take().unwrap();
+        for extra in &mut zones[head.zone + 1..head.zone + item.zones_needed()] {
+            extra.reserved_by = None;
         }
+        self.index.remove(head, &item);
+        item
     }
 
-    fn find_closest(&self, _item: &Item) -> Option<(usize, usize, usize)> {
+    // Stores the item at the given zone. Oversized items also reserve the
+    // zones after it on the same shelf, which must be free. The id may not
+    // belong to another product already stored.
+    pub fn add_zone(
+        &mut self,
+        item: Item,
+        row: usize,
+        shelf: usize,
+        zone: usize,
+    ) -> Result<(), WarehouseError> {
+        let loc = self.checked(row, shelf, zone)?;
+        self.check_span(loc, item.zones_needed(), None)?;
+        self.check_identity(&item)?;
+
+        self.record(Event::ItemStored {
+            row,
+            shelf,
+            zone,
+            item: item.clone(),
+        });
+        self.attach(loc, item);
+        Ok(())
+    }
+
+    // Empties the zone. When it is part of an oversized item the whole span
+    // is freed, whichever of its zones was given.
+    pub fn remove_zone(
+        &mut self,
+        row: usize,
+        shelf: usize,
+        zone: usize,
+    ) -> Result<Item, WarehouseError> {
+        let head = self.head_of(Location::new(row, shelf, zone))?;
+
+        self.record(Event::ItemRemoved { row, shelf, zone });
+        Ok(self.detach(head))
+    }
+
+    // Moves the item occupying `from` so that it starts at `to`. The units
+    // join the stock at `to` when it holds the same stock with room enough;
+    // otherwise the zones at `to` must be free. Spans may overlap the old one.
+    pub fn move_item(&mut self, from: Location, to: Location) -> Result<(), WarehouseError> {
+        self.relocate(from, to).map(|_| ())
+    }
+
+    // Does the work of `move_item` and returns the zone the item came from,
+    // with a copy of the item when its units joined the stock at `to`.
+    fn relocate(
+        &mut self,
+        from: Location,
+        to: Location,
+    ) -> Result<(Location, Option<Item>), WarehouseError> {
+        let head = self.head_of(from)?;
+        self.checked(to.row, to.shelf, to.zone)?;
+        if head == to {
+            return Ok((head, None));
+        }
+
+        let item = self.zone(head).item.as_ref().unwrap();
+        if !Constraints::for_item(item).allows(to) {
+            return Err(WarehouseError::NoCompatibleZone);
+        }
+        let target = self.zone(to);
+        let merge = match &target.item {
+            Some(stored) if stored.same_stock(item) && item.zones_needed() == 1 => {
+                if target.room() < item.quantity {
+                    return Err(WarehouseError::NoCapacity);
+                }
+                true
+            }
+            _ => {
+                self.check_span(to, item.zones_needed(), Some(head))?;
+                if item.zones_needed() == 1 && target.capacity < item.quantity {
+                    return Err(WarehouseError::NoCapacity);
+                }
+                false
+            }
+        };
+
+        self.record(Event::ItemMoved { from: head, to });
+
+        let item = self.detach(head);
+        if merge {
+            if let Some(stored) = &mut self.rows[to.row].shelves[to.shelf].zones[to.zone].item {
+                stored.quantity += item.quantity;
+            }
+            Ok((head, Some(item)))
+        } else {
+            self.attach(to, item);
+            Ok((head, None))
+        }
+    }
+
+    // Moves that pack every shelf: partial stacks of the same stock are
+    // merged, then items slide towards zone 0 so each shelf ends in one run
+    // of free zones, the room oversized goods need. Nothing is moved until
+    // the plan is applied.
+    pub fn compaction_plan(&self) -> MovePlan {
+        let mut moves = vec![];
+
         for (r_idx, row) in self.rows.iter().enumerate() {
             for (s_idx, shelf) in row.shelves.iter().enumerate() {
-                for (z_idx, zone) in shelf.zones.iter().enumerate() {
-                    if zone.item.is_none() {
-                        return Some((r_idx, s_idx, z_idx));
+                let at = |zone| Location::new(r_idx, s_idx, zone);
+
+                // (zone, units, item) for every item on the shelf, in order.
+                let mut stock: Vec<(usize, u32, &Item)> = shelf
+                    .zones
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(z, zone)| Some((z, zone.item.as_ref()?)))
+                    .map(|(z, item)| (z, item.quantity, item))
+                    .collect();
+
+                let mut i = 0;
+                while i < stock.len() {
+                    let (zone, _, item) = stock[i];
+                    let mut j = i + 1;
+                    while j < stock.len() {
+                        let (other, units, other_item) = stock[j];
+                        let room = shelf.zones[zone].capacity.saturating_sub(stock[i].1);
+                        if item.zones_needed() == 1 && other_item.same_stock(item) && units <= room
+                        {
+                            moves.push(Move {
+                                from: at(other),
+                                to: at(zone),
+                            });
+                            stock[i].1 += units;
+                            stock.remove(j);
+                        } else {
+                            j += 1;
+                        }
+                    }
+                    i += 1;
+                }
+
+                let mut next = 0;
+                for (zone, units, item) in stock {
+                    let fits = item.zones_needed() > 1 || units <= shelf.zones[next].capacity;
+                    if zone != next && fits {
+                        moves.push(Move {
+                            from: at(zone),
+                            to: at(next),
+                        });
+                        next += item.zones_needed();
+                    } else {
+                        next = zone + item.zones_needed();
                     }
                 }
             }
         }
-        None
+
+        MovePlan {
+            moves,
+            event_seq: self.event_seq,
+        }
+    }
+
+    // Carries out a plan from `compaction_plan`. A plan made before the
+    // warehouse last changed is refused. If a step fails, the steps before
+    // it are taken back in reverse, so a plan is never left half applied.
+    pub fn apply_plan(&mut self, plan: &MovePlan) -> Result<(), WarehouseError> {
+        if plan.event_seq != self.event_seq {
+            return Err(WarehouseError::StalePlan);
+        }
+
+        let mut done = vec![];
+        for step in &plan.moves {
+            match self.relocate(step.from, step.to) {
+                Ok((head, merged)) => done.push((head, step.to, merged)),
+                Err(e) => {
+                    for (head, to, merged) in done.into_iter().rev() {
+                        self.unrelocate(head, to, merged);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Takes back a step `relocate` made: the item goes back to `head` and
+    // merged units leave the stack at `to` again. It only ever restores the
+    // grid as it was, so nothing is checked and nothing can fail.
+    fn unrelocate(&mut self, head: Location, to: Location, merged: Option<Item>) {
+        let item = match merged {
+            Some(item) => {
+                self.take_units(to, item.quantity);
+                item
+            }
+            None => {
+                self.record(Event::ItemRemoved {
+                    row: to.row,
+                    shelf: to.shelf,
+                    zone: to.zone,
+                });
+                self.detach(to)
+            }
+        };
+
+        self.record(Event::ItemStored {
+            row: head.row,
+            shelf: head.shelf,
+            zone: head.zone,
+            item: item.clone(),
+        });
+        self.attach(head, item);
+    }
+
+    // Adds units to the item already stored at the zone, up to its capacity.
+    pub fn add_quantity(
+        &mut self,
+        row: usize,
+        shelf: usize,
+        zone: usize,
+        quantity: u32,
+    ) -> Result<(), WarehouseError> {
+        self.checked_item(row, shelf, zone)?;
+        if self.zone(Location::new(row, shelf, zone)).room() < quantity {
+            return Err(WarehouseError::NoCapacity);
+        }
+
+        self.record(Event::Restocked {
+            row,
+            shelf,
+            zone,
+            quantity,
+        });
+
+        if let Some(item) = &mut self.rows[row].shelves[shelf].zones[zone].item {
+            item.quantity += quantity;
+        }
+        Ok(())
+    }
+
+    // Takes units from the item stored at the zone, emptying the zone when
+    // none are left.
+    pub fn take_quantity(
+        &mut self,
+        row: usize,
+        shelf: usize,
+        zone: usize,
+        quantity: u32,
+    ) -> Result<(), WarehouseError> {
+        let item = self.checked_item(row, shelf, zone)?;
+        if quantity == 0 {
+            return Err(WarehouseError::ZeroQuantity);
+        }
+        if item.quantity < quantity {
+            return Err(WarehouseError::NotEnoughStock {
+                id: item.id,
+                requested: quantity as u64,
+                available: item.quantity as u64,
+            });
+        }
+
+        self.take_units(Location::new(row, shelf, zone), quantity);
+        Ok(())
     }
 
-    fn find_robin(&mut self, _item: &Item) -> Option<(usize, usize, usize)> {
-        let mut all_zones: Vec<(usize, usize, usize)> = vec![];
+    // Does the work of `take_quantity` once its checks have passed.
+    fn take_units(&mut self, loc: Location, quantity: u32) {
+        self.record(Event::Picked {
+            row: loc.row,
+            shelf: loc.shelf,
+            zone: loc.zone,
+            quantity,
+        });
+
+        let slot = &mut self.rows[loc.row].shelves[loc.shelf].zones[loc.zone];
+        if let Some(item) = &mut slot.item {
+            item.quantity -= quantity;
+        }
+        // The last units gone: free the zone, and the rest of an oversized span.
+        if slot.item.as_ref().is_some_and(|item| item.quantity == 0) {
+            self.detach(loc);
+        }
+    }
+
+    // Blocks the item stored at the zone from being picked. Quarantining an
+    // item twice changes nothing.
+    pub fn quarantine(
+        &mut self,
+        row: usize,
+        shelf: usize,
+        zone: usize,
+    ) -> Result<(), WarehouseError> {
+        if self.checked_item(row, shelf, zone)?.quarantined {
+            return Ok(());
+        }
+
+        self.record(Event::Quarantined { row, shelf, zone });
+
+        if let Some(item) = &mut self.rows[row].shelves[shelf].zones[zone].item {
+            item.quarantined = true;
+        }
+        Ok(())
+    }
+
+    // Quarantines every fragile item past its expiry date that is not
+    // quarantined yet, and returns where they are.
+    pub fn quarantine_expired(&mut self) -> Result<Vec<Location>, WarehouseError> {
+        let yesterday = self.today() - Duration::days(1);
+        let expired: Vec<Location> = self
+            .index
+            .expiring_by(yesterday)
+            .into_iter()
+            .map(|(_, loc)| loc)
+            .filter(|loc| {
+                self.zone(*loc)
+                    .item
+                    .as_ref()
+                    .is_some_and(|item| !item.quarantined)
+            })
+            .collect();
+
+        for loc in &expired {
+            self.quarantine(loc.row, loc.shelf, loc.zone)?;
+        }
+        Ok(expired)
+    }
+
+    // Picks `quantity` units of the item with this id from however many zones
+    // hold it, oldest stock first (FIFO) or soonest to expire first (FEFO).
+    // Quarantined stock is left alone. Nothing is taken unless there is
+    // enough stock.
+    pub fn pick(
+        &mut self,
+        id: u32,
+        quantity: u32,
+        order: PickOrder,
+    ) -> Result<Vec<Placement>, WarehouseError> {
+        let picks = self.plan_pick(id, quantity as u64, order)?;
+        for pick in &picks {
+            let loc = pick.location;
+            self.take_quantity(loc.row, loc.shelf, loc.zone, pick.quantity)?;
+        }
+        Ok(picks)
+    }
+
+    // The picks `pick` would make, without taking anything.
+    pub fn plan_pick(
+        &self,
+        id: u32,
+        quantity: u64,
+        order: PickOrder,
+    ) -> Result<Vec<Placement>, WarehouseError> {
+        let mut stock = self.pickable(id);
+        let available: u64 = stock.iter().map(|(_, item)| item.quantity as u64).sum();
+        if available < quantity {
+            return Err(WarehouseError::NotEnoughStock {
+                id,
+                requested: quantity,
+                available,
+            });
+        }
+
+        match order {
+            PickOrder::Fifo => stock.sort_by_key(|(loc, item)| (item.timestamp, *loc)),
+            PickOrder::Fefo => stock.sort_by_key(|(loc, item)| {
+                (
+                    item.expiry_date().is_none(),
+                    item.expiry_date(),
+                    item.lot.clone(),
+                    item.timestamp,
+                    *loc,
+                )
+            }),
+        }
+
+        let mut remaining = quantity;
+        let mut picks = vec![];
+
+        for (loc, item) in stock {
+            if remaining == 0 {
+                break;
+            }
+            let taken = (item.quantity as u64).min(remaining);
+            picks.push(Placement {
+                location: loc,
+                quantity: taken as u32,
+            });
+            remaining -= taken;
+        }
+
+        Ok(picks)
+    }
+
+    // Stock of the item with this id that `pick` may take from. Oversized
+    // goods count too: taking their last units frees the whole span.
+    fn pickable(&self, id: u32) -> Vec<(Location, &Item)> {
+        self.find_by_id(id)
+            .into_iter()
+            .filter_map(|loc| Some((loc, self.zone(loc).item.as_ref()?)))
+            .filter(|(_, item)| !item.quarantined)
+            .collect()
+    }
+
+    // Units of the item with this id that can be picked.
+    pub fn available(&self, id: u32) -> u64 {
+        self.pickable(id)
+            .iter()
+            .map(|(_, item)| item.quantity as u64)
+            .sum()
+    }
+
+    pub fn zone(&self, loc: Location) -> &Zone {
+        &self.rows[loc.row].shelves[loc.shelf].zones[loc.zone]
+    }
+
+    // Runs the item through every filter and, if it passes, stores it. Units
+    // first top up zones already holding the same stock, the rest goes to
+    // zones chosen by the allocation strategy, split by zone capacity.
+    // Nothing is stored unless all units fit.
+    pub fn store(
+        &mut self,
+        item: Item,
+        filters: &[Box<dyn Filter>],
+    ) -> Result<Vec<Placement>, WarehouseError> {
+        for filter in filters {
+            filter
+                .apply(self, &item)
+                .map_err(WarehouseError::Rejected)?;
+        }
+        if item.is_expired(self.today()) {
+            return Err(WarehouseError::Expired(item.id));
+        }
+        self.check_identity(&item)?;
+        if item.quantity == 0 {
+            return Err(WarehouseError::ZeroQuantity);
+        }
+
+        // Oversized goods take a whole span and are never stacked or split.
+        if item.zones_needed() > 1 {
+            let loc = self.find_zone(&item)?;
+            let quantity = item.quantity;
+            self.add_zone(item, loc.row, loc.shelf, loc.zone)?;
+            return Ok(vec![Placement {
+                location: loc,
+                quantity,
+            }]);
+        }
+
+        let constraints = Constraints::for_item(&item);
+        let stacks: Vec<(Location, u32)> = self
+            .find_by_id(item.id)
+            .into_iter()
+            .filter(|loc| {
+                let zone = self.zone(*loc);
+                zone.item
+                    .as_ref()
+                    .is_some_and(|stored| stored.same_stock(&item))
+                    && constraints.allows(*loc)
+            })
+            .map(|loc| (loc, self.zone(loc).room()))
+            .filter(|(_, room)| *room > 0)
+            .collect();
+
+        let stack_room: u64 = stacks.iter().map(|(_, room)| *room as u64).sum();
+        if stack_room + self.free_capacity(&constraints) < item.quantity as u64 {
+            let unconstrained = Constraints {
+                max_row: None,
+                ..constraints
+            };
+            return Err(
+                if stack_room + self.free_capacity(&unconstrained) < item.quantity as u64 {
+                    WarehouseError::NoCapacity
+                } else {
+                    WarehouseError::NoCompatibleZone
+                },
+            );
+        }
+
+        let mut remaining = item.quantity;
+        let mut placements = vec![];
+
+        for (loc, room) in stacks {
+            if remaining == 0 {
+                break;
+            }
+            let quantity = room.min(remaining);
+            self.add_quantity(loc.row, loc.shelf, loc.zone, quantity)?;
+            placements.push(Placement {
+                location: loc,
+                quantity,
+            });
+            remaining -= quantity;
+        }
+
+        while remaining > 0 {
+            let loc = self.find_zone(&item)?;
+            let quantity = self.zone(loc).capacity.min(remaining);
+            let mut part = item.clone();
+            part.quantity = quantity;
+            self.add_zone(part, loc.row, loc.shelf, loc.zone)?;
+            placements.push(Placement {
+                location: loc,
+                quantity,
+            });
+            remaining -= quantity;
+        }
+
+        Ok(placements)
+    }
+
+    // Takes back what `store` just placed, last placement first. Oversized
+    // goods were placed with all their units, so they leave their zones
+    // whole. The placements must come straight from `store`: the grid is
+    // put back as it was, so nothing is checked and nothing can fail.
+    pub(crate) fn unstore(&mut self, placements: &[Placement]) {
+        for placement in placements.iter().rev() {
+            self.take_units(placement.location, placement.quantity);
+        }
+    }
+
+    // Units that still fit in the free zones these constraints allow.
+    fn free_capacity(&self, constraints: &Constraints) -> u64 {
+        self.candidates(constraints)
+            .iter()
+            .map(|loc| self.zone(*loc).capacity as u64)
+            .sum()
+    }
+
+    pub fn items(&self) -> Vec<(Location, &Item)> {
+        let mut items = vec![];
 
         for (r_idx, row) in self.rows.iter().enumerate() {
             for (s_idx, shelf) in row.shelves.iter().enumerate() {
-                for (z_idx, _zone) in shelf.zones.iter().enumerate() {
-                    all_zones.push((r_idx, s_idx, z_idx));
+                for (z_idx, zone) in shelf.zones.iter().enumerate() {
+                    if let Some(item) = &zone.item {
+                        items.push((Location::new(r_idx, s_idx, z_idx), item));
+                    }
                 }
             }
         }
 
-        let total = all_zones.len();
-        for i in 0..total {
-            let index = (self.last_used_index + i) % total;
-            let (r, s, z) = all_zones[index];
+        items
+    }
+
+    // Zones holding the item with this id, in grid order.
+    pub fn find_by_id(&self, id: u32) -> Vec<Location> {
+        self.index.by_id(id)
+    }
 
-            if self.rows[r].shelves[s].zones[z].item.is_none() {
-                self.last_used_index = (index + 1) % total;
-                return Some((r, s, z));
+    // Zones holding items with this name, ignoring case, in grid order.
+    pub fn find_by_name(&self, name: &str) -> Vec<Location> {
+        self.index.by_name(name)
+    }
+
+    // The stock of the item with this id per lot, soonest to expire first,
+    // then oldest received.
+    pub fn lots(&self, id: u32) -> Vec<Lot> {
+        let mut lots: Vec<Lot> = vec![];
+
+        for loc in self.find_by_id(id) {
+            let Some(item) = self.zone(loc).item.as_ref() else {
+                continue;
+            };
+            let found = lots
+                .iter_mut()
+                .find(|lot| lot.lot == item.lot && lot.expiry_date == item.expiry_date());
+            match found {
+                Some(lot) => {
+                    lot.received = lot.received.min(item.timestamp);
+                    lot.units += item.quantity as u64;
+                    lot.locations.push(loc);
+                }
+                None => lots.push(Lot {
+                    lot: item.lot.clone(),
+                    expiry_date: item.expiry_date(),
+                    received: item.timestamp,
+                    units: item.quantity as u64,
+                    locations: vec![loc],
+                }),
             }
         }
-        None
+
+        lots.sort_by_key(|lot| (lot.expiry_date.is_none(), lot.expiry_date, lot.received));
+        lots
     }
 
-    pub fn add_zone(&mut self, item: Item, row: usize, shelf: usize, zone: usize) {
-        self.rows[row].shelves[shelf].zones[zone].item = Some(item);
+    pub fn count_by_name(&self, name: &str) -> usize {
+        self.find_by_name(name).len()
+    }
+
+    pub fn items_by_name(&self) -> Vec<&Item> {
+        self.index
+            .sorted_by_name()
+            .into_iter()
+            .filter_map(|loc| self.zone(loc).item.as_ref())
+            .collect()
+    }
+
+    // Items whose expiry date is at most `days` away, grouped by the days
+    // left, soonest first. Expired items lead with a negative number of days.
+    // A threshold past either end of the calendar stops at that end.
+    pub fn expiry_report(&self, days: i64) -> Vec<(i64, Vec<(Location, &Item)>)> {
+        let today = self.today();
+        let limit = Duration::try_days(days)
+            .and_then(|ahead| today.checked_add_signed(ahead))
+            .unwrap_or(if days < 0 {
+                NaiveDate::MIN
+            } else {
+                NaiveDate::MAX
+            });
+        let mut groups: Vec<(i64, Vec<(Location, &Item)>)> = vec![];
+
+        for (date, loc) in self.index.expiring_by(limit) {
+            let Some(item) = self.zone(loc).item.as_ref() else {
+                continue;
+            };
+            let days_left = (date - today).num_days();
+            match groups.last_mut() {
+                Some((group, items)) if *group == days_left => items.push((loc, item)),
+                _ => groups.push((days_left, vec![(loc, item)])),
+            }
+        }
+
+        groups
     }
 }
 
+// Snapshots store the strategy through its StrategySpec.
+mod strategy_serde {
+    use crate::strategy::{AllocationStrategy, StrategySpec};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    // serde's `with` hands over a reference to the field itself.
+    #[allow(clippy::borrowed_box)]
+    pub fn serialize<S: Serializer>(
+        strategy: &Box<dyn AllocationStrategy>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        strategy.spec().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Box<dyn AllocationStrategy>, D::Error> {
+        Ok(StrategySpec::deserialize(deserializer)?.build())
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
 pub struct Row {
     pub shelves: Vec<Shelf>,
 }
@@ -98,6 +1207,7 @@ impl Row {
     }
 }
 
+#[derive(Default, Serialize, Deserialize)]
 pub struct Shelf {
     pub zones: Vec<Zone>,
 }
@@ -112,12 +1222,726 @@ impl Shelf {
     }
 }
 
+pub const DEFAULT_ZONE_CAPACITY: u32 = 100;
+
+fn default_capacity() -> u32 {
+    DEFAULT_ZONE_CAPACITY
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct Zone {
     pub item: Option<Item>,
+    // Set on the extra zones taken by an oversized item: the index of the
+    // zone on the same shelf that holds the item itself.
+    #[serde(default)]
+    pub reserved_by: Option<usize>,
+    // Units of one item the zone can hold. Oversized items ignore it.
+    #[serde(default = "default_capacity")]
+    pub capacity: u32,
 }
 
 impl Zone {
-    pub fn new() -> Zone {
-        Zone { item: None }
+    pub fn with_capacity(capacity: u32) -> Zone {
+        Zone {
+            item: None,
+            reserved_by: None,
+            capacity,
+        }
+    }
+
+    pub fn is_free(&self) -> bool {
+        self.item.is_none() && self.reserved_by.is_none()
+    }
+
+    // Units that can still be added to the item in this zone.
+    pub fn room(&self) -> u32 {
+        match (&self.item, self.reserved_by) {
+            (_, Some(_)) => 0,
+            (Some(item), None) => self.capacity.saturating_sub(item.quantity),
+            (None, None) => self.capacity,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{parse_date, FixedClock};
+    use crate::item::Quality;
+    use crate::strategy::RoundRobin;
+    use crate::test_support::{place, warehouse_with};
+
+    fn item(id: u32, quality: Quality) -> Item {
+        Item::new(id, format!("Item {}", id), 1, quality, DateTime::UNIX_EPOCH)
+    }
+
+    fn oversized(id: u32, zones_needed: u32) -> Item {
+        item(id, Quality::Oversized { zones_needed })
+    }
+
+    #[test]
+    fn test_oversized_takes_contiguous_zones_on_one_shelf() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "row 3 3");
+        warehouse
+            .add_zone(item(1, Quality::Normal), 0, 0, 1)
+            .unwrap();
+
+        // Shelf 0 only has runs of one free zone, so the span goes to shelf 1.
+        let loc = place(&mut warehouse, oversized(2, 2)).unwrap();
+        assert_eq!(loc, Location::new(0, 1, 0));
+
+        let zones = &warehouse.rows[0].shelves[1].zones;
+        assert_eq!(zones[0].item.as_ref().unwrap().id, 2);
+        assert_eq!(zones[1].reserved_by, Some(0));
+        assert!(zones[2].is_free());
+
+        // Normal items skip the reserved zone.
+        let loc = place(&mut warehouse, item(3, Quality::Normal)).unwrap();
+        assert_eq!(loc, Location::new(0, 0, 0));
+        let loc = place(&mut warehouse, item(4, Quality::Normal)).unwrap();
+        assert_eq!(loc, Location::new(0, 0, 2));
+        let loc = place(&mut warehouse, item(5, Quality::Normal)).unwrap();
+        assert_eq!(loc, Location::new(0, 1, 2));
+    }
+
+    #[test]
+    fn test_oversized_without_room_is_refused() {
+        let mut warehouse = warehouse_with(Box::new(RoundRobin { next: 0 }), "row 2 2");
+        let mut empty = oversized(1, 2);
+        empty.quantity = 0;
+        assert_eq!(
+            place(&mut warehouse, empty),
+            Err(WarehouseError::ZeroQuantity)
+        );
+        assert!(warehouse
+            .all_locations()
+            .iter()
+            .all(|loc| warehouse.zone(*loc).is_free()));
+
+        assert!(place(&mut warehouse, oversized(1, 3)).is_err());
+        assert!(place(&mut warehouse, oversized(1, 2)).is_ok());
+        assert!(place(&mut warehouse, oversized(2, 2)).is_ok());
+        assert!(place(&mut warehouse, item(3, Quality::Normal)).is_err());
+    }
+
+    #[test]
+    fn test_fragile_items_stay_at_or_below_their_row() {
+        let fragile = |id| {
+            item(
+                id,
+                Quality::Fragile {
+                    expiry_date: parse_date("2099-01-01").unwrap(),
+                    max_shelf: 1,
+                },
+            )
+        };
+
+        let strategies: [Box<dyn AllocationStrategy>; 2] =
+            [Box::new(Closest), Box::new(RoundRobin { next: 0 })];
+        for strategy in strategies {
+            let mut warehouse = warehouse_with(strategy, "grid 3 1 1");
+            assert_eq!(
+                place(&mut warehouse, item(1, Quality::Normal)),
+                Ok(Location::new(0, 0, 0))
+            );
+            assert_eq!(
+                place(&mut warehouse, fragile(2)),
+                Ok(Location::new(1, 0, 0))
+            );
+            assert_eq!(
+                warehouse.find_zone(&fragile(3)),
+                Err(WarehouseError::NoCompatibleZone)
+            );
+            assert_eq!(
+                place(&mut warehouse, item(4, Quality::Normal)),
+                Ok(Location::new(2, 0, 0))
+            );
+            assert_eq!(
+                warehouse.find_zone(&fragile(5)),
+                Err(WarehouseError::NoCapacity)
+            );
+        }
+    }
+
+    #[test]
+    fn test_same_stock_stacks_then_overflows_into_new_zones() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "capacity 10\nrow 3");
+        let mut bolts = item(1, Quality::Normal);
+        bolts.quantity = 4;
+        warehouse.store(bolts.clone(), &[]).unwrap();
+
+        bolts.quantity = 12;
+        let placements = warehouse.store(bolts.clone(), &[]).unwrap();
+        assert_eq!(
+            placements,
+            vec![
+                Placement {
+                    location: Location::new(0, 0, 0),
+                    quantity: 6
+                },
+                Placement {
+                    location: Location::new(0, 0, 1),
+                    quantity: 6
+                },
+            ]
+        );
+
+        // A different item never shares a zone.
+        let mut nuts = item(2, Quality::Normal);
+        nuts.quantity = 3;
+        assert_eq!(place(&mut warehouse, nuts), Ok(Location::new(0, 0, 2)));
+    }
+
+    #[test]
+    fn test_store_is_all_or_nothing() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "capacity 10\nrow 2");
+        let mut bolts = item(1, Quality::Normal);
+        bolts.quantity = 21;
+
+        assert!(warehouse.store(bolts.clone(), &[]).is_err());
+        assert!(warehouse.items().is_empty());
+
+        bolts.quantity = 20;
+        assert_eq!(warehouse.store(bolts, &[]).unwrap().len(), 2);
+        assert!(warehouse.store(item(1, Quality::Normal), &[]).is_err());
+    }
+
+    #[test]
+    fn test_pick_fifo_and_fefo() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "row 3");
+        let batch = |expiry: &str, received: i64, quantity| {
+            let quality = Quality::Fragile {
+                expiry_date: parse_date(expiry).unwrap(),
+                max_shelf: 0,
+            };
+            Item::new(
+                1,
+                "Milk".to_string(),
+                quantity,
+                quality,
+                DateTime::from_timestamp(received, 0).unwrap(),
+            )
+        };
+        warehouse
+            .add_zone(batch("2030-03-01", 100, 5), 0, 0, 0)
+            .unwrap();
+        warehouse
+            .add_zone(batch("2030-01-01", 300, 5), 0, 0, 1)
+            .unwrap();
+        warehouse
+            .add_zone(batch("2030-02-01", 200, 5), 0, 0, 2)
+            .unwrap();
+
+        let picks = warehouse.pick(1, 7, PickOrder::Fifo).unwrap();
+        let taken: Vec<(usize, u32)> = picks
+            .iter()
+            .map(|p| (p.location.zone, p.quantity))
+            .collect();
+        assert_eq!(taken, vec![(0, 5), (2, 2)]);
+        assert!(warehouse.zone(Location::new(0, 0, 0)).is_free());
+
+        let picks = warehouse.pick(1, 6, PickOrder::Fefo).unwrap();
+        let taken: Vec<(usize, u32)> = picks
+            .iter()
+            .map(|p| (p.location.zone, p.quantity))
+            .collect();
+        assert_eq!(taken, vec![(1, 5), (2, 1)]);
+        assert_eq!(
+            warehouse
+                .zone(Location::new(0, 0, 2))
+                .item
+                .as_ref()
+                .unwrap()
+                .quantity,
+            2
+        );
+    }
+
+    #[test]
+    fn test_pick_fails_without_enough_stock() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "row 2");
+        let mut bolts = item(1, Quality::Normal);
+        bolts.quantity = 3;
+        warehouse.add_zone(bolts, 0, 0, 1).unwrap();
+
+        assert!(warehouse.pick(1, 4, PickOrder::Fifo).is_err());
+        assert_eq!(
+            warehouse
+                .zone(Location::new(0, 0, 1))
+                .item
+                .as_ref()
+                .unwrap()
+                .quantity,
+            3
+        );
+        assert!(warehouse.pick(2, 1, PickOrder::Fifo).is_err());
+    }
+
+    #[test]
+    fn test_oversized_stock_is_picked_like_any_other() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "row 3");
+        let mut crates = oversized(1, 2);
+        crates.quantity = 3;
+        warehouse.add_zone(crates, 0, 0, 1).unwrap();
+        assert_eq!(warehouse.available(1), 3);
+
+        let picks = warehouse.pick(1, 2, PickOrder::Fifo).unwrap();
+        assert_eq!(
+            picks,
+            vec![Placement {
+                location: Location::new(0, 0, 1),
+                quantity: 2
+            }]
+        );
+        assert_eq!(warehouse.zone(Location::new(0, 0, 2)).reserved_by, Some(1));
+
+        warehouse.pick(1, 1, PickOrder::Fifo).unwrap();
+        assert!(warehouse.rows[0].shelves[0].zones.iter().all(Zone::is_free));
+        assert_eq!(warehouse.available(1), 0);
+    }
+
+    #[test]
+    fn test_expired_stock_is_reported_and_quarantined() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "row 4");
+        warehouse.set_clock(Box::new(FixedClock::on(parse_date("2030-01-10").unwrap())));
+        let milk = |expiry: &str, quantity| {
+            let quality = Quality::Fragile {
+                expiry_date: parse_date(expiry).unwrap(),
+                max_shelf: 0,
+            };
+            Item::new(
+                1,
+                "Milk".to_string(),
+                quantity,
+                quality,
+                DateTime::UNIX_EPOCH,
+            )
+        };
+        warehouse.add_zone(milk("2030-01-08", 5), 0, 0, 0).unwrap();
+        warehouse.add_zone(milk("2030-01-12", 5), 0, 0, 1).unwrap();
+        warehouse.add_zone(milk("2030-01-12", 5), 0, 0, 2).unwrap();
+        warehouse.add_zone(milk("2030-01-20", 5), 0, 0, 3).unwrap();
+
+        let groups: Vec<(i64, usize)> = warehouse
+            .expiry_report(3)
+            .iter()
+            .map(|(days_left, items)| (*days_left, items.len()))
+            .collect();
+        assert_eq!(groups, vec![(-2, 1), (2, 2)]);
+        assert_eq!(warehouse.expiry_report(100_000_000).len(), 3);
+        assert_eq!(warehouse.expiry_report(i64::MAX).len(), 3);
+        assert!(warehouse.expiry_report(i64::MIN).is_empty());
+
+        assert_eq!(
+            warehouse.quarantine_expired().unwrap(),
+            vec![Location::new(0, 0, 0)]
+        );
+        assert!(warehouse.quarantine_expired().unwrap().is_empty());
+        assert_eq!(
+            warehouse.pick(1, 16, PickOrder::Fefo),
+            Err(WarehouseError::NotEnoughStock {
+                id: 1,
+                requested: 16,
+                available: 15,
+            })
+        );
+        let picks = warehouse.pick(1, 6, PickOrder::Fefo).unwrap();
+        assert_eq!(picks[0].location, Location::new(0, 0, 1));
+        assert!(warehouse.zone(Location::new(0, 0, 0)).item.is_some());
+    }
+
+    #[test]
+    fn test_indexes_follow_store_pick_and_remove() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "capacity 5\nrow 4");
+        let mut milk = item(
+            1,
+            Quality::Fragile {
+                expiry_date: parse_date("2099-01-01").unwrap(),
+                max_shelf: 0,
+            },
+        );
+        milk.name = "Milk".to_string();
+        milk.quantity = 8;
+        warehouse.store(milk, &[]).unwrap();
+        place(&mut warehouse, oversized(2, 2)).unwrap();
+
+        let scan = |warehouse: &Warehouse, id| {
+            warehouse
+                .items()
+                .into_iter()
+                .filter(|(_, item)| item.id == id)
+                .map(|(loc, _)| loc)
+                .collect::<Vec<Location>>()
+        };
+        assert_eq!(warehouse.find_by_id(1), scan(&warehouse, 1));
+        assert_eq!(warehouse.find_by_name("MILK").len(), 2);
+        assert_eq!(warehouse.find_by_id(2), vec![Location::new(0, 0, 2)]);
+        warehouse.set_clock(Box::new(FixedClock::on(parse_date("2098-12-30").unwrap())));
+        let report = warehouse.expiry_report(2);
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].0, 2);
+        assert_eq!(report[0].1.len(), 2);
+        assert!(warehouse.expiry_report(1).is_empty());
+
+        warehouse.pick(1, 5, PickOrder::Fifo).unwrap();
+        assert_eq!(warehouse.find_by_id(1), vec![Location::new(0, 0, 1)]);
+        warehouse.remove_zone(0, 0, 3).unwrap();
+        assert!(warehouse.find_by_id(2).is_empty());
+        assert_eq!(warehouse.count_by_name("item 2"), 0);
+    }
+
+    #[test]
+    fn test_mutators_report_typed_errors() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "row 3");
+        assert_eq!(
+            warehouse.add_zone(item(1, Quality::Normal), 0, 1, 0),
+            Err(WarehouseError::OutOfBounds(Location::new(0, 1, 0)))
+        );
+        assert_eq!(
+            warehouse.add_zone(oversized(1, 2), 0, 0, 2),
+            Err(WarehouseError::OutOfBounds(Location::new(0, 0, 3)))
+        );
+        warehouse
+            .add_zone(item(1, Quality::Normal), 0, 0, 1)
+            .unwrap();
+        assert_eq!(
+            warehouse.add_zone(oversized(2, 2), 0, 0, 0),
+            Err(WarehouseError::ZoneOccupied(Location::new(0, 0, 1)))
+        );
+        assert_eq!(
+            warehouse.take_quantity(0, 0, 0, 1),
+            Err(WarehouseError::ZoneEmpty(Location::new(0, 0, 0)))
+        );
+
+        let mut other = item(1, Quality::Normal);
+        other.name = "Something else".to_string();
+        assert_eq!(
+            warehouse.store(other, &[]),
+            Err(WarehouseError::DuplicateId(1))
+        );
+
+        let expired = item(
+            3,
+            Quality::Fragile {
+                expiry_date: parse_date("2000-01-01").unwrap(),
+                max_shelf: 0,
+            },
+        );
+        assert_eq!(
+            warehouse.store(expired.clone(), &[]),
+            Err(WarehouseError::Expired(3))
+        );
+        assert_eq!(
+            warehouse.store(expired, &crate::filter::default_filters()),
+            Err(WarehouseError::Rejected(Rejection::new(
+                "expiration",
+                "the item expired on 2000-01-01".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_an_id_names_one_product() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "row 4");
+        let milk = |name: &str, expiry: &str| {
+            let quality = Quality::Fragile {
+                expiry_date: parse_date(expiry).unwrap(),
+                max_shelf: 0,
+            };
+            Item::new(1, name.to_string(), 5, quality, DateTime::UNIX_EPOCH)
+        };
+
+        place(&mut warehouse, milk("Milk", "2099-01-01")).unwrap();
+        assert_eq!(
+            place(&mut warehouse, milk("milk", "2099-02-01")),
+            Ok(Location::new(0, 0, 1))
+        );
+        assert_eq!(
+            place(&mut warehouse, milk("Cream", "2099-01-01")),
+            Err(WarehouseError::DuplicateId(1))
+        );
+        assert_eq!(
+            place(&mut warehouse, item(1, Quality::Normal)),
+            Err(WarehouseError::DuplicateId(1))
+        );
+        assert_eq!(
+            warehouse.add_zone(item(1, Quality::Normal), 0, 0, 3),
+            Err(WarehouseError::DuplicateId(1))
+        );
+        assert_eq!(warehouse.find_by_id(1).len(), 2);
+    }
+
+    #[test]
+    fn test_lots_are_kept_apart_and_picked_soonest_first() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "capacity 10\nrow 5");
+        let milk = |lot: &str, expiry: &str, received: i64, quantity| {
+            let quality = Quality::Fragile {
+                expiry_date: parse_date(expiry).unwrap(),
+                max_shelf: 0,
+            };
+            let timestamp = DateTime::from_timestamp(received, 0).unwrap();
+            Item::new(1, "Milk".to_string(), quantity, quality, timestamp).with_lot(lot)
+        };
+
+        warehouse
+            .store(milk("B", "2099-03-01", 100, 6), &[])
+            .unwrap();
+        warehouse
+            .store(milk("A", "2099-02-01", 200, 12), &[])
+            .unwrap();
+        warehouse
+            .store(milk("C", "2099-02-01", 300, 3), &[])
+            .unwrap();
+        assert_eq!(
+            warehouse.store(milk("A", "2099-04-01", 400, 1), &[]),
+            Err(WarehouseError::LotMismatch {
+                id: 1,
+                lot: "A".to_string(),
+            })
+        );
+
+        let lots: Vec<(String, u64, usize)> = warehouse
+            .lots(1)
+            .iter()
+            .map(|lot| (lot.lot.clone().unwrap(), lot.units, lot.locations.len()))
+            .collect();
+        assert_eq!(
+            lots,
+            vec![
+                ("A".to_string(), 12, 2),
+                ("C".to_string(), 3, 1),
+                ("B".to_string(), 6, 1),
+            ]
+        );
+
+        let picks = warehouse.pick(1, 14, PickOrder::Fefo).unwrap();
+        let zones: Vec<(usize, u32)> = picks
+            .iter()
+            .map(|p| (p.location.zone, p.quantity))
+            .collect();
+        assert_eq!(zones, vec![(1, 10), (2, 2), (3, 2)]);
+        assert_eq!(warehouse.lots(1)[0].lot.as_deref(), Some("C"));
+    }
+
+    #[test]
+    fn test_move_item_between_zones() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "capacity 10\nrow 4\nrow 2");
+        warehouse.add_zone(oversized(1, 2), 0, 0, 1).unwrap();
+        warehouse
+            .add_zone(item(2, Quality::Normal), 1, 0, 0)
+            .unwrap();
+
+        // An oversized item can shift over its own span.
+        warehouse
+            .move_item(Location::new(0, 0, 2), Location::new(0, 0, 0))
+            .unwrap();
+        let zones = &warehouse.rows[0].shelves[0].zones;
+        assert_eq!(zones[0].item.as_ref().unwrap().id, 1);
+        assert_eq!(zones[1].reserved_by, Some(0));
+        assert!(zones[2].is_free() && zones[3].is_free());
+        assert_eq!(warehouse.find_by_id(1), vec![Location::new(0, 0, 0)]);
+
+        assert_eq!(
+            warehouse.move_item(Location::new(1, 0, 0), Location::new(0, 0, 1)),
+            Err(WarehouseError::ZoneOccupied(Location::new(0, 0, 1)))
+        );
+        assert_eq!(
+            warehouse.move_item(Location::new(0, 0, 0), Location::new(1, 0, 1)),
+            Err(WarehouseError::OutOfBounds(Location::new(1, 0, 2)))
+        );
+
+        // Units of the same stock join the stack already there.
+        warehouse
+            .add_zone(item(2, Quality::Normal), 0, 0, 3)
+            .unwrap();
+        warehouse
+            .move_item(Location::new(0, 0, 3), Location::new(1, 0, 0))
+            .unwrap();
+        assert_eq!(warehouse.find_by_id(2), vec![Location::new(1, 0, 0)]);
+        assert_eq!(
+            warehouse
+                .zone(Location::new(1, 0, 0))
+                .item
+                .as_ref()
+                .unwrap()
+                .quantity,
+            2
+        );
+    }
+
+    #[test]
+    fn test_compaction_plan_packs_shelves() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "capacity 10\nrow 6");
+        let mut bolts = item(1, Quality::Normal);
+        bolts.quantity = 4;
+        warehouse.add_zone(bolts.clone(), 0, 0, 1).unwrap();
+        warehouse.add_zone(oversized(2, 2), 0, 0, 2).unwrap();
+        warehouse.add_zone(bolts, 0, 0, 5).unwrap();
+
+        let plan = warehouse.compaction_plan();
+        assert_eq!(
+            plan.moves,
+            vec![
+                Move {
+                    from: Location::new(0, 0, 5),
+                    to: Location::new(0, 0, 1)
+                },
+                Move {
+                    from: Location::new(0, 0, 1),
+                    to: Location::new(0, 0, 0)
+                },
+                Move {
+                    from: Location::new(0, 0, 2),
+                    to: Location::new(0, 0, 1)
+                },
+            ]
+        );
+        // Nothing moves until the plan is applied.
+        assert_eq!(warehouse.find_by_id(1).len(), 2);
+
+        warehouse.apply_plan(&plan).unwrap();
+        assert_eq!(warehouse.find_by_id(1), vec![Location::new(0, 0, 0)]);
+        assert_eq!(warehouse.find_by_id(2), vec![Location::new(0, 0, 1)]);
+        assert!(warehouse.span_is_free(Location::new(0, 0, 3), 3));
+        assert!(warehouse.compaction_plan().moves.is_empty());
+    }
+
+    #[test]
+    fn test_stale_plan_is_refused() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "row 3");
+        warehouse
+            .add_zone(item(1, Quality::Normal), 0, 0, 2)
+            .unwrap();
+        let plan = warehouse.compaction_plan();
+        warehouse
+            .add_zone(item(2, Quality::Normal), 0, 0, 0)
+            .unwrap();
+
+        assert_eq!(warehouse.apply_plan(&plan), Err(WarehouseError::StalePlan));
+        assert_eq!(warehouse.find_by_id(1), vec![Location::new(0, 0, 2)]);
+    }
+
+    #[test]
+    fn test_failed_plan_step_undoes_the_earlier_ones() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "row 4");
+        warehouse
+            .add_zone(item(1, Quality::Normal), 0, 0, 0)
+            .unwrap();
+        warehouse
+            .add_zone(item(1, Quality::Normal), 0, 0, 1)
+            .unwrap();
+        warehouse
+            .add_zone(item(2, Quality::Normal), 0, 0, 3)
+            .unwrap();
+
+        // A merge, a plain move, then a step from a zone that is empty.
+        let plan = MovePlan {
+            moves: vec![
+                Move {
+                    from: Location::new(0, 0, 1),
+                    to: Location::new(0, 0, 0),
+                },
+                Move {
+                    from: Location::new(0, 0, 3),
+                    to: Location::new(0, 0, 1),
+                },
+                Move {
+                    from: Location::new(0, 0, 2),
+                    to: Location::new(0, 0, 3),
+                },
+            ],
+            event_seq: warehouse.event_seq(),
+        };
+        assert_eq!(
+            warehouse.apply_plan(&plan),
+            Err(WarehouseError::ZoneEmpty(Location::new(0, 0, 2)))
+        );
+
+        assert_eq!(
+            warehouse.find_by_id(1),
+            vec![Location::new(0, 0, 0), Location::new(0, 0, 1)]
+        );
+        assert_eq!(warehouse.find_by_id(2), vec![Location::new(0, 0, 3)]);
+        let quantities: Vec<u32> = warehouse
+            .items()
+            .iter()
+            .map(|(_, item)| item.quantity)
+            .collect();
+        assert_eq!(quantities, vec![1, 1, 1]);
+    }
+
+    #[test]
+    fn test_plan_undo_cannot_fail_on_the_way_back() {
+        // Stored by hand above its row limit: a move back there would be
+        // refused, but undoing the plan still puts it back.
+        let mut warehouse = warehouse_with(Box::new(Closest), "row 2\nrow 2");
+        let milk = item(
+            1,
+            Quality::Fragile {
+                expiry_date: parse_date("2099-01-01").unwrap(),
+                max_shelf: 0,
+            },
+        );
+        warehouse.add_zone(milk, 1, 0, 0).unwrap();
+
+        let plan = MovePlan {
+            moves: vec![
+                Move {
+                    from: Location::new(1, 0, 0),
+                    to: Location::new(0, 0, 0),
+                },
+                Move {
+                    from: Location::new(1, 0, 1),
+                    to: Location::new(0, 0, 1),
+                },
+            ],
+            event_seq: warehouse.event_seq(),
+        };
+        assert_eq!(
+            warehouse.apply_plan(&plan),
+            Err(WarehouseError::ZoneEmpty(Location::new(1, 0, 1)))
+        );
+        assert_eq!(warehouse.find_by_id(1), vec![Location::new(1, 0, 0)]);
+        assert!(warehouse.zone(Location::new(0, 0, 0)).is_free());
+
+        warehouse
+            .move_item(Location::new(1, 0, 0), Location::new(0, 0, 0))
+            .unwrap();
+        assert_eq!(
+            warehouse.move_item(Location::new(0, 0, 0), Location::new(1, 0, 0)),
+            Err(WarehouseError::NoCompatibleZone)
+        );
+    }
+
+    #[test]
+    fn test_removing_any_zone_of_a_span_frees_it_all() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "row 3");
+        place(&mut warehouse, oversized(1, 3)).unwrap();
+
+        let removed = warehouse.remove_zone(0, 0, 2).unwrap();
+        assert_eq!(removed.id, 1);
+        assert!(warehouse.rows[0].shelves[0].zones.iter().all(Zone::is_free));
+        assert_eq!(
+            warehouse.remove_zone(0, 0, 0).unwrap_err(),
+            WarehouseError::ZoneEmpty(Location::new(0, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_taking_the_last_units_frees_the_whole_span() {
+        let mut warehouse = warehouse_with(Box::new(Closest), "row 3");
+        place(&mut warehouse, oversized(1, 3)).unwrap();
+
+        assert_eq!(
+            warehouse.take_quantity(0, 0, 0, 0),
+            Err(WarehouseError::ZeroQuantity)
+        );
+        assert_eq!(warehouse.event_seq(), 1);
+        warehouse.take_quantity(0, 0, 0, 1).unwrap();
+        assert!(warehouse.rows[0].shelves[0].zones.iter().all(Zone::is_free));
+        assert!(warehouse.find_by_id(1).is_empty());
+
+        let loc = place(&mut warehouse, oversized(2, 3)).unwrap();
+        assert_eq!(loc, Location::new(0, 0, 0));
     }
 }